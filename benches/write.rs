@@ -0,0 +1,125 @@
+//! Measures how fast record batches of each common column type are written to a worksheet.
+//!
+//! Run with `cargo bench`. Each case writes the same batches several times and reports the
+//! fastest run, as the time to save the workbook is not part of writing the cells. The last
+//! case compares the column-by-column writing of the default mode with the row-by-row writing of
+//! constant memory mode.
+
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use arrow::array::{
+    ArrayRef, BooleanArray, Decimal128Array, Float64Array, Int64Array, StringArray,
+    TimestampMillisecondArray,
+};
+use arrow::record_batch::RecordBatch;
+use rs_arrow_ipc_stream2x::{Error, WriteOptions, batch_iter2x_with_options};
+use rust_xlsxwriter::Workbook;
+
+/// The rows of each batch.
+const BATCH_ROWS: usize = 10_000;
+
+/// The batches written by each run.
+const BATCHES: usize = 20;
+
+/// The runs of each case, of which the fastest is reported.
+const RUNS: usize = 5;
+
+/// Builds a batch of the named column types, each with a null in every tenth row.
+fn columns(offset: usize) -> Result<Vec<(&'static str, ArrayRef)>, Error> {
+    let rows = offset..offset + BATCH_ROWS;
+    let valid = |row: &usize| !row.is_multiple_of(10);
+    let decimals: Decimal128Array = rows
+        .clone()
+        .map(|row| valid(&row).then_some(row as i128 * 125))
+        .collect();
+    Ok(vec![
+        (
+            "int",
+            Arc::new(Int64Array::from_iter(
+                rows.clone().map(|row| valid(&row).then_some(row as i64)),
+            )),
+        ),
+        (
+            "float",
+            Arc::new(Float64Array::from_iter(
+                rows.clone()
+                    .map(|row| valid(&row).then_some(row as f64 / 3.0)),
+            )),
+        ),
+        (
+            "string",
+            Arc::new(StringArray::from_iter(
+                rows.clone()
+                    .map(|row| valid(&row).then(|| format!("row {row}"))),
+            )),
+        ),
+        (
+            "bool",
+            Arc::new(BooleanArray::from_iter(
+                rows.clone()
+                    .map(|row| valid(&row).then_some(row.is_multiple_of(3))),
+            )),
+        ),
+        (
+            "timestamp",
+            Arc::new(TimestampMillisecondArray::from_iter(rows.clone().map(
+                |row| valid(&row).then_some(1_700_000_000_000 + row as i64 * 1_000),
+            ))),
+        ),
+        (
+            "decimal",
+            Arc::new(decimals.with_precision_and_scale(18, 2)?),
+        ),
+    ])
+}
+
+/// Builds the batches of a case, of the named column or of all columns.
+fn batches(column: Option<&str>) -> Result<Vec<RecordBatch>, Error> {
+    (0..BATCHES)
+        .map(|index| {
+            let columns = columns(index * BATCH_ROWS)?
+                .into_iter()
+                .filter(|(name, _)| column.is_none_or(|column| *name == column));
+            Ok(RecordBatch::try_from_iter(columns)?)
+        })
+        .collect()
+}
+
+/// Writes the batches to a new workbook `RUNS` times and prints the fastest run.
+fn bench(name: &str, batches: &[RecordBatch], options: &WriteOptions) -> Result<(), Error> {
+    let mut fastest = Duration::MAX;
+    for _ in 0..RUNS {
+        let mut book = Workbook::new();
+        let start = Instant::now();
+        let summary = batch_iter2x_with_options(
+            batches.iter().cloned().map(Ok),
+            &mut book,
+            "Bench",
+            options,
+        )?;
+        fastest = fastest.min(start.elapsed());
+        black_box((summary, book));
+    }
+    let cells = (BATCHES * BATCH_ROWS * batches[0].num_columns()) as f64;
+    println!(
+        "{name:<24} {:>8.1} ms {:>8.2} M cells/s",
+        fastest.as_secs_f64() * 1e3,
+        cells / fastest.as_secs_f64() / 1e6
+    );
+    Ok(())
+}
+
+fn main() -> Result<(), Error> {
+    let options = WriteOptions::default();
+    for column in ["int", "float", "string", "bool", "timestamp", "decimal"] {
+        bench(column, &batches(Some(column))?, &options)?;
+    }
+    let all = batches(None)?;
+    bench("all", &all, &options)?;
+    // Constant memory mode writes row by row rather than column by column.
+    let options = WriteOptions::builder().constant_memory(true).build();
+    bench("all, constant memory", &all, &options)?;
+    Ok(())
+}
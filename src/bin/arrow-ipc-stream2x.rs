@@ -1,46 +1,695 @@
+use std::collections::HashSet;
 use std::fs::File;
-use std::io::{self, BufReader, Read};
+use std::io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::net::TcpStream;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::Arc;
 
-use arrow::ipc::reader::StreamReader;
-use clap::Parser;
-use rust_xlsxwriter::Workbook;
+use arrow::array::timezone::Tz;
+use arrow::csv::reader::{Format, ReaderBuilder};
+use arrow::datatypes::Schema;
+use arrow::ipc::reader::{FileReader, StreamReader};
+use arrow::record_batch::RecordBatchReader;
+use clap::{Parser, ValueEnum};
+use flate2::read::MultiGzDecoder;
+use rust_xlsxwriter::{DocProperties, Workbook};
 
-use rs_arrow_ipc_stream2x::batch_iter2x;
+use rs_arrow_ipc_stream2x::{
+    ConcatStreamReader, Error, MAX_CELL_CHARS, WriteOptions, batch_iter2x_with_schema,
+    buffer_magic, is_ipc_file, sanitize_sheet_name, save_to_writer, suffixed_sheet_name,
+};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum InputFormat {
+    /// Detect the format from the leading magic bytes
+    Auto,
+    /// Arrow IPC stream format
+    Stream,
+    /// Arrow IPC file (random access) format
+    File,
+    /// Parquet file
+    #[cfg(feature = "parquet")]
+    Parquet,
+    /// CSV with the column types inferred from the first 1000 records
+    Csv,
+}
+
+/// The number of CSV records read to infer the column types.
+const CSV_INFER_RECORDS: usize = 1000;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    /// Input Arrow IPC stream file. If not specified, reads from stdin.
+    /// Input Arrow IPC stream or file, Parquet file or CSV file, or `tcp://host:port` to read
+    /// Arrow IPC from a TCP connection. Repeat to write several inputs to their own worksheets.
+    /// If not specified, reads from stdin.
     #[arg(short, long)]
-    input: Option<String>,
+    input: Vec<String>,
+
+    /// Input format
+    #[arg(short, long, value_enum, default_value_t = InputFormat::Auto)]
+    format: InputFormat,
 
-    /// Output Excel file
+    /// Decompress gzip-compressed Arrow IPC input. Implied by an input path ending in `.gz`.
+    #[arg(long)]
+    gzip: bool,
+
+    /// Read Arrow IPC stream input as several streams written back to back, to one worksheet
+    #[arg(long)]
+    concat_streams: bool,
+
+    /// Output Excel file. If not specified or `-`, writes to stdout.
     #[arg(short, long)]
-    output: String,
+    output: Option<String>,
 
-    /// Sheet name
+    /// Sheet name of the input with the same position. Inputs without one are named after
+    /// their file name, or `Sheet1` for stdin. A name already used by an earlier input gets a
+    /// ` (2)`, ` (3)`, ... suffix.
     #[arg(short, long)]
-    sheet: String,
+    sheet: Vec<String>,
+
+    /// Comma-separated names of the columns to write, in order. If not specified, writes all
+    /// columns.
+    #[arg(long, value_delimiter = ',')]
+    columns: Option<Vec<String>>,
+
+    /// Comma-separated names written in the header row in place of the column names, one per
+    /// written column.
+    #[arg(long, value_delimiter = ',')]
+    headers: Option<Vec<String>>,
+
+    /// Comma-separated names of string columns whose values starting with `=` are written as
+    /// formulas
+    #[arg(long, value_delimiter = ',')]
+    formula_columns: Option<Vec<String>>,
+
+    /// Comma-separated names of columns whose runs of equal consecutive values are merged into
+    /// one cell. Ignored in constant memory mode.
+    #[arg(long, value_delimiter = ',')]
+    merge_runs: Option<Vec<String>>,
+
+    /// The most characters written to a string cell; longer strings end with `…`
+    #[arg(long, default_value_t = MAX_CELL_CHARS)]
+    max_cell_chars: usize,
+
+    /// Field delimiter of CSV input
+    #[arg(long, default_value_t = ',')]
+    csv_delimiter: char,
+
+    /// Whether the first line of CSV input holds the column names
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    csv_has_header: bool,
+
+    /// Excel number format of date cells
+    #[arg(long, default_value = "yyyy-mm-dd")]
+    date_format: String,
+
+    /// Maximum number of data rows to write for each input
+    #[arg(long)]
+    max_rows: Option<u64>,
+
+    /// Number of leading data rows to skip for each input
+    #[arg(long, default_value_t = 0)]
+    skip_rows: u64,
+
+    /// Schema metadata key whose value names the worksheet, falling back to `--sheet` if the
+    /// schema has no such key. A name already used by an earlier input is numbered as for
+    /// `--sheet`.
+    #[arg(long, value_name = "KEY")]
+    sheet_from_metadata: Option<String>,
+
+    /// Number of leading columns to freeze so they stay visible while scrolling right
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    freeze_columns: u16,
+
+    /// Title of the workbook document properties
+    #[arg(long)]
+    title: Option<String>,
+
+    /// Author of the workbook document properties
+    #[arg(long)]
+    author: Option<String>,
+
+    /// Company of the workbook document properties
+    #[arg(long)]
+    company: Option<String>,
+
+    /// Protect the worksheets against accidental edits. This is not encryption: the file can
+    /// still be read by anyone.
+    #[arg(long)]
+    protect: bool,
+
+    /// Password required to unprotect the worksheets
+    #[arg(long, requires = "protect")]
+    password: Option<String>,
+
+    /// Zero-based indices of the first and last record batches to write for each input, such as
+    /// `2:5`
+    #[arg(long, value_name = "START:END", value_parser = parse_batch_range)]
+    batch_range: Option<RangeInclusive<usize>>,
+
+    /// Text written in place of null values, such as `\N`, to tell them apart from empty strings
+    #[arg(long)]
+    null_text: Option<String>,
+
+    /// Write rows to disk as they are completed rather than keeping the workbook in memory.
+    /// Enabled automatically for input files that are estimated to be large: files of 64 MiB,
+    /// gzip files of 8 MiB, and Parquet or Arrow IPC files of ten million cells. Stdin and TCP
+    /// input are only written this way with this flag.
+    #[arg(long)]
+    constant_memory: bool,
+
+    /// Timezone that timestamps without one are converted to, such as `Asia/Tokyo` or `+09:00`.
+    /// If not specified, they are written as UTC.
+    #[arg(long, value_name = "TZ", value_parser = parse_tz)]
+    naive_timestamp_tz: Option<Tz>,
+
+    /// Report the rows and worksheets written to stderr. Repeat to report each batch as well.
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Report nothing but errors to stderr
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+/// Writes log records to stderr, prefixed with their level.
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            let level = record.level().as_str().to_ascii_lowercase();
+            eprintln!("{}: {}", level, record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Sets up [`StderrLogger`] with the level of `--quiet` and `--verbose`.
+fn init_logger(args: &Args) -> Result<(), log::SetLoggerError> {
+    let level = match (args.quiet, args.verbose) {
+        (true, _) => log::LevelFilter::Error,
+        (false, 0) => log::LevelFilter::Warn,
+        (false, 1) => log::LevelFilter::Info,
+        (false, _) => log::LevelFilter::Debug,
+    };
+    log::set_logger(&LOGGER)?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+/// Input files of at least this many bytes are written in constant memory mode.
+const CONSTANT_MEMORY_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Gzip-compressed input files of at least this many bytes are written in constant memory mode,
+/// as Arrow IPC data commonly shrinks to an eighth of its size or less.
+const CONSTANT_MEMORY_GZIP_BYTES: u64 = CONSTANT_MEMORY_BYTES / 8;
+
+/// Parquet and Arrow IPC files of at least this many cells are written in constant memory mode,
+/// as their size says little about the size of the data if they are compressed.
+const CONSTANT_MEMORY_CELLS: u64 = 10_000_000;
+
+/// Estimates whether an input file is large enough to be written in constant memory mode, from
+/// its size, scaled for gzip-compressed input, or from the row count in the metadata of a Parquet
+/// or Arrow IPC file. Stdin cannot be estimated without reading it, so it never is.
+fn is_large_input(args: &Args, input: Option<&str>) -> bool {
+    let Some(input_path) = input else {
+        return false;
+    };
+    let file_len = || std::fs::metadata(input_path).map_or(0, |metadata| metadata.len());
+    match args.format {
+        #[cfg(feature = "parquet")]
+        InputFormat::Parquet => {
+            parquet_cells(input_path).is_some_and(|cells| cells >= CONSTANT_MEMORY_CELLS)
+        }
+        InputFormat::Csv => file_len() >= CONSTANT_MEMORY_BYTES,
+        _ if is_gzip(args, input) => file_len() >= CONSTANT_MEMORY_GZIP_BYTES,
+        _ => {
+            file_len() >= CONSTANT_MEMORY_BYTES
+                || ipc_file_cells(input_path).is_some_and(|cells| cells >= CONSTANT_MEMORY_CELLS)
+        }
+    }
+}
+
+/// Returns the number of cells of an Arrow IPC file from the record batch messages listed in its
+/// footer, without reading the data, or `None` if it is not an Arrow IPC file.
+fn ipc_file_cells(input_path: &str) -> Option<u64> {
+    use arrow::ipc::reader::read_footer_length;
+    use arrow::ipc::{root_as_footer, root_as_message};
+
+    let mut file = File::open(input_path).ok()?;
+    let mut magic = [0; 6];
+    file.read_exact(&mut magic).ok()?;
+    if !is_ipc_file(&mut &magic[..]).ok()? {
+        return None;
+    }
+    let mut trailer = [0; 10];
+    file.seek(SeekFrom::End(-10)).ok()?;
+    file.read_exact(&mut trailer).ok()?;
+    let mut footer = vec![0; read_footer_length(trailer).ok()?];
+    file.seek(SeekFrom::End(-10 - i64::try_from(footer.len()).ok()?))
+        .ok()?;
+    file.read_exact(&mut footer).ok()?;
+    let footer = root_as_footer(&footer).ok()?;
+    let columns = footer.schema()?.fields()?.len() as u64;
+
+    let mut rows = 0;
+    for block in footer.recordBatches()? {
+        let mut metadata = vec![0; usize::try_from(block.metaDataLength()).ok()?];
+        file.seek(SeekFrom::Start(u64::try_from(block.offset()).ok()?))
+            .ok()?;
+        file.read_exact(&mut metadata).ok()?;
+        // The message follows its length, which files written before Arrow 0.15 do not precede
+        // with the continuation marker.
+        let start = if metadata.starts_with(&[0xff; 4]) {
+            8
+        } else {
+            4
+        };
+        let message = root_as_message(metadata.get(start..)?).ok()?;
+        rows += u64::try_from(message.header_as_record_batch()?.length()).ok()?;
+    }
+    Some(rows * columns)
+}
+
+/// Returns the number of cells of a Parquet file from its metadata, without reading the data.
+#[cfg(feature = "parquet")]
+fn parquet_cells(input_path: &str) -> Option<u64> {
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(input_path).ok()?).ok()?;
+    let metadata = builder.metadata().file_metadata();
+    let rows = u64::try_from(metadata.num_rows()).ok()?;
+    Some(rows * metadata.schema_descr().num_columns() as u64)
+}
+
+/// Parses a `START:END` batch range, both inclusive.
+fn parse_batch_range(range: &str) -> Result<RangeInclusive<usize>, String> {
+    let (start, end) = range
+        .split_once(':')
+        .ok_or("expected START:END, such as 2:5")?;
+    let start: usize = start.trim().parse().map_err(|e| format!("START: {e}"))?;
+    let end: usize = end.trim().parse().map_err(|e| format!("END: {e}"))?;
+    if start > end {
+        return Err(format!("START {start} is after END {end}"));
+    }
+    Ok(start..=end)
+}
+
+fn parse_tz(tz: &str) -> Result<Tz, String> {
+    tz.parse()
+        .map_err(|e: arrow::error::ArrowError| e.to_string())
+}
+
+/// The magic bytes at the start of a Feather v1 file.
+///
+/// Feather v2 is the Arrow IPC file format and is read as [`InputFormat::File`], but arrow has no
+/// reader for the legacy v1 layout, so such input is rejected with a hint instead of failing as a
+/// malformed stream.
+const FEATHER_V1_MAGIC: &[u8] = b"FEA1";
+
+/// Resolves [`InputFormat::Auto`] by peeking at the buffered input, which must hold the leading
+/// bytes, such as after [`buffer_magic`].
+fn resolve_format<R: BufRead>(
+    format: InputFormat,
+    reader: &mut R,
+) -> Result<InputFormat, Box<dyn std::error::Error>> {
+    if reader.fill_buf()?.starts_with(FEATHER_V1_MAGIC) {
+        return Err(
+            "Feather v1 input is not supported; rewrite it as Feather v2, such as with \
+             pyarrow.feather.write_feather(table, path, version=2)"
+                .into(),
+        );
+    }
+    match format {
+        InputFormat::Auto if is_ipc_file(reader)? => Ok(InputFormat::File),
+        InputFormat::Auto => Ok(InputFormat::Stream),
+        format => Ok(format),
+    }
+}
+
+#[cfg(feature = "parquet")]
+fn open_parquet_reader(
+    input: Option<&str>,
+) -> Result<Box<dyn RecordBatchReader>, Box<dyn std::error::Error>> {
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    if let Some(input_path) = input {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(input_path)?)?;
+        return Ok(Box::new(builder.build()?));
+    }
+
+    // Parquet metadata is at the end of the file, so stdin is read into memory first.
+    let mut buf = Vec::new();
+    io::stdin().read_to_end(&mut buf)?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(buf))?;
+    Ok(Box::new(builder.build()?))
+}
+
+/// Opens CSV input, inferring the column types from the first [`CSV_INFER_RECORDS`] records.
+///
+/// A column is inferred as text if any of those records does not parse as another type, but
+/// a record beyond them that does not fit the inferred type fails the conversion.
+fn open_csv_reader(
+    args: &Args,
+    input: Option<&str>,
+) -> Result<Box<dyn RecordBatchReader>, Box<dyn std::error::Error>> {
+    let delimiter = u8::try_from(args.csv_delimiter)
+        .ok()
+        .filter(u8::is_ascii)
+        .ok_or("the CSV delimiter must be an ASCII character")?;
+    let format = Format::default()
+        .with_header(args.csv_has_header)
+        .with_delimiter(delimiter);
+
+    match input {
+        Some(input_path) => csv_reader(BufReader::new(File::open(input_path)?), format),
+        None => {
+            // Inference reads the leading records, so stdin is read into memory to read them
+            // again.
+            let mut buf = Vec::new();
+            io::stdin().read_to_end(&mut buf)?;
+            csv_reader(Cursor::new(buf), format)
+        }
+    }
+}
+
+fn csv_reader<R: Read + Seek + 'static>(
+    mut reader: R,
+    format: Format,
+) -> Result<Box<dyn RecordBatchReader>, Box<dyn std::error::Error>> {
+    let (schema, _) = format.infer_schema(&mut reader, Some(CSV_INFER_RECORDS))?;
+    reader.rewind()?;
+    let csv_reader = ReaderBuilder::new(Arc::new(schema))
+        .with_format(format)
+        .build(reader)?;
+    Ok(Box::new(csv_reader))
+}
+
+fn open_reader(
+    args: &Args,
+    input: Option<&str>,
+) -> Result<Box<dyn RecordBatchReader>, Box<dyn std::error::Error>> {
+    match args.format {
+        #[cfg(feature = "parquet")]
+        InputFormat::Parquet => open_parquet_reader(input),
+        InputFormat::Csv => open_csv_reader(args, input),
+        format => open_ipc_reader(input, format, is_gzip(args, input), args.concat_streams),
+    }
+}
+
+/// Returns whether the Arrow IPC input is gzip-compressed, as set by `--gzip` or implied by a
+/// path ending in `.gz`.
+fn is_gzip(args: &Args, input: Option<&str>) -> bool {
+    args.gzip || input.is_some_and(|path| path.ends_with(".gz"))
+}
+
+fn open_ipc_reader(
+    input: Option<&str>,
+    format: InputFormat,
+    gzip: bool,
+    concat: bool,
+) -> Result<Box<dyn RecordBatchReader>, Box<dyn std::error::Error>> {
+    if let Some(address) = input.and_then(tcp_address) {
+        let stream = connect(address)?;
+        if gzip {
+            return sequential_ipc_reader(MultiGzDecoder::new(stream), format, concat);
+        }
+        return sequential_ipc_reader(stream, format, concat);
+    }
+
+    if gzip {
+        let reader: Box<dyn Read> = match input {
+            Some(input_path) => Box::new(File::open(input_path)?),
+            None => Box::new(io::stdin()),
+        };
+        return sequential_ipc_reader(MultiGzDecoder::new(reader), format, concat);
+    }
+
+    if let Some(input_path) = input {
+        let mut buf_reader = BufReader::new(File::open(input_path)?);
+        if resolve_format(format, &mut buf_reader)? == InputFormat::File {
+            return Ok(Box::new(FileReader::try_new(buf_reader, None)?));
+        }
+        return stream_reader(buf_reader, concat);
+    }
+
+    sequential_ipc_reader(io::stdin(), format, concat)
+}
+
+/// Returns the `host:port` of a `tcp://host:port` input.
+fn tcp_address(input: &str) -> Option<&str> {
+    input.strip_prefix("tcp://")
+}
+
+/// Connects to a `host:port` address, naming it in the error if that fails.
+fn connect(address: &str) -> io::Result<TcpStream> {
+    TcpStream::connect(address)
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot connect to {address}: {e}")))
+}
+
+/// Opens Arrow IPC input that can only be read front to back, such as stdin.
+fn sequential_ipc_reader<R: Read + 'static>(
+    reader: R,
+    format: InputFormat,
+    concat: bool,
+) -> Result<Box<dyn RecordBatchReader>, Box<dyn std::error::Error>> {
+    let mut buf_reader = buffer_magic(reader)?;
+    if resolve_format(format, &mut buf_reader)? == InputFormat::File {
+        // The file format needs to seek, so the input is read into memory first.
+        let mut buf = Vec::new();
+        buf_reader.read_to_end(&mut buf)?;
+        return Ok(Box::new(FileReader::try_new(Cursor::new(buf), None)?));
+    }
+    stream_reader(buf_reader, concat)
+}
+
+fn stream_reader<R: BufRead + 'static>(
+    reader: R,
+    concat: bool,
+) -> Result<Box<dyn RecordBatchReader>, Box<dyn std::error::Error>> {
+    if concat {
+        return Ok(Box::new(ConcatStreamReader::try_new(reader)?));
+    }
+    Ok(Box::new(StreamReader::try_new(reader, None)?))
+}
+
+/// Resolves column names to their indices in the schema.
+fn resolve_columns(schema: &Schema, names: &[String]) -> Result<Vec<usize>, Error> {
+    names
+        .iter()
+        .map(|name| {
+            schema.index_of(name).map_err(|_| Error::ColumnNotFound {
+                name: name.clone(),
+                available: schema.fields().iter().map(|f| f.name().clone()).collect(),
+            })
+        })
+        .collect()
+}
+
+/// Names the worksheet of an input without a sheet name after the file name up to its first
+/// `.`, so `sales.arrows.gz` becomes `sales`, or after the address of a TCP input.
+fn default_sheet_name(input: Option<&str>) -> String {
+    if let Some(address) = input.and_then(tcp_address) {
+        return address.replace(':', "_");
+    }
+    input
+        .and_then(|path| Path::new(path).file_name())
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split('.').next())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("Sheet1")
+        .into()
+}
+
+/// Builds the document properties from the `--title`, `--author` and `--company` flags, or `None`
+/// if none of them is set to a non-empty value.
+fn doc_properties(args: &Args) -> Option<DocProperties> {
+    let non_empty = |value: &Option<String>| value.clone().filter(|value| !value.is_empty());
+    let (title, author, company) = (
+        non_empty(&args.title),
+        non_empty(&args.author),
+        non_empty(&args.company),
+    );
+    if title.is_none() && author.is_none() && company.is_none() {
+        return None;
+    }
+
+    let mut properties = DocProperties::new();
+    if let Some(title) = title {
+        properties = properties.set_title(title);
+    }
+    if let Some(author) = author {
+        properties = properties.set_author(author);
+    }
+    if let Some(company) = company {
+        properties = properties.set_company(company);
+    }
+    Some(properties)
+}
+
+/// Returns the sheet name, or the first of `name (2)`, `name (3)`, ... that is not used yet.
+///
+/// The used names are lowercase, as Excel compares sheet names case-insensitively.
+fn unique_sheet_name(name: &str, used_names: &HashSet<String>) -> String {
+    let mut unique = name.to_string();
+    let mut number = 1;
+    while used_names.contains(&unique.to_lowercase()) {
+        number += 1;
+        unique = suffixed_sheet_name(name, &format!(" ({number})"));
+    }
+    unique
+}
+
+/// Writes one input to its own worksheet, adding the names of the written worksheets to
+/// `used_names`.
+fn write_input(
+    workbook: &mut Workbook,
+    args: &Args,
+    input: Option<&str>,
+    sheet: &str,
+    options: &WriteOptions,
+    used_names: &mut HashSet<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let ipc_reader = open_reader(args, input)?;
+
+    let schema = ipc_reader.schema();
+    let sheet = args
+        .sheet_from_metadata
+        .as_ref()
+        .and_then(|key| schema.metadata().get(key))
+        .map_or(sheet, String::as_str);
+    let projection = match &args.columns {
+        Some(names) => Some(resolve_columns(&schema, names)?),
+        None => None,
+    };
+    let schema = match &projection {
+        Some(indices) => schema.project(indices)?,
+        None => schema.as_ref().clone(),
+    };
+    let batches = ipc_reader.map(|batch| match &projection {
+        Some(indices) => batch?.project(indices),
+        None => batch,
+    });
+
+    let sheet_name = unique_sheet_name(&sanitize_sheet_name(sheet), used_names);
+    if sheet_name != sheet {
+        log::warn!("sheet name {:?} was changed to {:?}", sheet, sheet_name);
+    }
+
+    let summary = batch_iter2x_with_schema(batches, &schema, workbook, &sheet_name, options)?;
+    used_names.extend(
+        workbook
+            .worksheets()
+            .iter()
+            .map(|worksheet| worksheet.name().to_lowercase()),
+    );
+    log::info!(
+        "{}: wrote {} rows to {} worksheets",
+        input.unwrap_or("stdin"),
+        summary.rows_written,
+        summary.sheets_written
+    );
+    if summary.truncated_cells > 0 {
+        log::warn!(
+            "{}: shortened {} long text cells",
+            input.unwrap_or("stdin"),
+            summary.truncated_cells
+        );
+    }
+    Ok(())
+}
+
+fn write_options(args: &Args, constant_memory: bool) -> WriteOptions {
+    let mut builder = WriteOptions::builder()
+        .date_format(args.date_format.clone())
+        .skip_rows(args.skip_rows)
+        .freeze_columns(args.freeze_columns)
+        .constant_memory(constant_memory);
+    if let Some(max_rows) = args.max_rows {
+        builder = builder.max_rows(max_rows);
+    }
+    if let Some(null_text) = &args.null_text {
+        builder = builder.null_text(null_text);
+    }
+    if let Some(batch_range) = &args.batch_range {
+        builder = builder.batch_range(batch_range.clone());
+    }
+    if args.protect {
+        builder = builder.protect(args.password.clone());
+    }
+    if let Some(headers) = &args.headers {
+        builder = builder.header_overrides(headers.clone());
+    }
+    if let Some(formula_columns) = &args.formula_columns {
+        builder = builder.formula_columns(formula_columns.clone());
+    }
+    if let Some(merge_runs) = &args.merge_runs {
+        builder = builder.merge_runs(merge_runs.clone());
+    }
+    builder = builder.max_cell_chars(args.max_cell_chars);
+    if let Some(tz) = args.naive_timestamp_tz {
+        builder = builder.naive_timestamp_tz(tz);
+    }
+    builder.build()
 }
 
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
+    init_logger(&args)?;
 
-    let reader: Box<dyn Read> = if let Some(input_path) = args.input {
-        Box::new(File::open(input_path)?)
+    let inputs: Vec<Option<&str>> = if args.input.is_empty() {
+        vec![None]
     } else {
-        Box::new(io::stdin())
+        args.input.iter().map(|path| Some(path.as_str())).collect()
     };
 
-    let buf_reader = BufReader::new(reader);
-
-    let ipc_reader = StreamReader::try_new(buf_reader, None)?;
-
     let mut workbook = Workbook::new();
+    if let Some(properties) = doc_properties(&args) {
+        workbook.set_properties(&properties);
+    }
+    let mut used_names = HashSet::new();
 
-    batch_iter2x(ipc_reader, &mut workbook, &args.sheet)?;
+    for (index, input) in inputs.into_iter().enumerate() {
+        let sheet = match args.sheet.get(index) {
+            Some(sheet) => sheet.clone(),
+            None => default_sheet_name(input),
+        };
+        let large_input = !args.constant_memory && is_large_input(&args, input);
+        if large_input && args.merge_runs.is_some() {
+            log::warn!(
+                "{}: --merge-runs is ignored, as the large input is written in constant memory mode",
+                input.unwrap_or("stdin")
+            );
+        }
+        let constant_memory = args.constant_memory || large_input;
+        let options = write_options(&args, constant_memory);
+        write_input(
+            &mut workbook,
+            &args,
+            input,
+            &sheet,
+            &options,
+            &mut used_names,
+        )?;
+    }
 
-    workbook.save(args.output)?;
+    match args.output.as_deref() {
+        Some(output_path) if output_path != "-" => {
+            workbook.save(output_path)?;
+            log::info!("saved the workbook to {}", output_path);
+        }
+        _ => save_to_writer(&mut workbook, io::stdout().lock())?,
+    }
 
     Ok(())
 }
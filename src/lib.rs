@@ -1,13 +1,55 @@
-use arrow::array::{Array, as_boolean_array, as_primitive_array, as_string_array};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Chain, Cursor, Read, Write};
+use std::ops::{Range, RangeInclusive};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use arrow::array::timezone::Tz;
+use arrow::array::{
+    Array, ArrayRef, AsArray, BooleanArray, PrimitiveArray, StringArray, StructArray, UInt32Array,
+    UInt64Array, make_array, make_comparator,
+};
+use arrow::buffer::NullBuffer;
+use arrow::compute::{SortOptions, cast, filter_record_batch, take_record_batch};
 use arrow::datatypes::*;
-use arrow::record_batch::RecordBatch;
-use chrono::{DateTime, NaiveDate, Utc};
-use rust_xlsxwriter::{Workbook, XlsxError};
+use arrow::ipc::reader::StreamReader;
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+use arrow::util::display::{ArrayFormatter, FormatOptions};
+use base64::prelude::{BASE64_STANDARD, Engine};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, Utc};
+use rust_xlsxwriter::utility::{cell_range, worksheet_range_absolute};
+use rust_xlsxwriter::{
+    ConditionalFormat3ColorScale, Format, FormatAlign, Formula, IntoExcelDateTime, Note, Table,
+    TableColumn, TableStyle, Workbook, Worksheet, XlsxError,
+};
 
 #[derive(Debug)]
 pub enum Error {
     Xlsx(XlsxError),
     Arrow(arrow::error::ArrowError),
+    Io(std::io::Error),
+    TooManyColumns {
+        found: usize,
+        max: usize,
+    },
+    SchemaMismatch {
+        expected: SchemaRef,
+        found: SchemaRef,
+    },
+    ColumnNotFound {
+        name: String,
+        available: Vec<String>,
+    },
+    StartCellOutOfRange {
+        row: u32,
+        col: u16,
+    },
+    UnsupportedType(DataType),
+    HeaderCountMismatch {
+        expected: usize,
+        found: usize,
+    },
 }
 
 impl From<XlsxError> for Error {
@@ -22,242 +64,3452 @@ impl From<arrow::error::ArrowError> for Error {
     }
 }
 
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Error::Xlsx(e) => write!(f, "Xlsx error: {}", e),
             Error::Arrow(e) => write!(f, "Arrow error: {}", e),
+            Error::Io(e) => write!(f, "IO error: {}", e),
+            Error::TooManyColumns { found, max } => {
+                write!(
+                    f,
+                    "Too many columns: {} (Excel allows at most {})",
+                    found, max
+                )
+            }
+            Error::SchemaMismatch { expected, found } => {
+                write!(
+                    f,
+                    "Schema mismatch: expected [{}], found [{}]",
+                    expected, found
+                )
+            }
+            Error::ColumnNotFound { name, available } => {
+                write!(
+                    f,
+                    "Column not found: {} (available: {})",
+                    name,
+                    available.join(", ")
+                )
+            }
+            Error::StartCellOutOfRange { row, col } => {
+                write!(
+                    f,
+                    "Start cell out of range: row {}, column {} leaves no room for data",
+                    row, col
+                )
+            }
+            Error::UnsupportedType(data_type) => write!(f, "Unsupported data type: {}", data_type),
+            Error::HeaderCountMismatch { expected, found } => {
+                write!(
+                    f,
+                    "Header count mismatch: {} header overrides for {} columns",
+                    found, expected
+                )
+            }
         }
     }
 }
 
 impl std::error::Error for Error {}
 
-pub fn batch_iter2x<I>(mut bi: I, book: &mut Workbook, sheet_name: &str) -> Result<(), Error>
+/// Options controlling how record batches are written to a worksheet.
+///
+/// The default options match the behavior of [`batch_iter2x`].
+///
+/// Workbooks always use the 1900 date system. The 1904 system, used by default in old Mac
+/// versions of Excel, is not offered, as rust_xlsxwriter has no setting for it; dates before
+/// 1900 are written as ISO 8601 text instead.
+#[derive(Debug, Clone)]
+pub struct WriteOptions {
+    write_header: bool,
+    null_text: Option<String>,
+    date_format: String,
+    bool_as_text: bool,
+    bool_labels: (String, String),
+    freeze_header: bool,
+    header_format: Option<Format>,
+    autofit: bool,
+    constant_memory: bool,
+    duration_as_seconds: bool,
+    binary_encoding: BinaryEncoding,
+    nan_text: String,
+    infinity_text: String,
+    neg_infinity_text: String,
+    sheet_per_batch: bool,
+    start_row: u32,
+    start_col: u16,
+    autofilter: bool,
+    table_style: Option<TableStyleOption>,
+    transpose: bool,
+    totals_row: bool,
+    detect_hyperlinks: bool,
+    max_rows: Option<u64>,
+    skip_rows: u64,
+    strict: bool,
+    freeze_columns: u16,
+    numbers_as_text: bool,
+    /// `Some` to protect the worksheets, with the password if it is `Some` as well.
+    protect: Option<Option<String>>,
+    decimal_grouping: bool,
+    color_scale_numeric: bool,
+    sheet_namer: Option<SheetNamer>,
+    row_numbers: bool,
+    batch_range: Option<RangeInclusive<usize>>,
+    emit_schema_sheet: bool,
+    header_overrides: Vec<String>,
+    cell_writer: Option<CustomCellWriter>,
+    title: Option<String>,
+    define_name: Option<String>,
+    naive_timestamp_tz: Option<Tz>,
+    formula_columns: Vec<String>,
+    merge_runs: Vec<String>,
+    max_cell_chars: usize,
+    notes_column: Option<(String, String)>,
+}
+
+/// A [`TableStyle`], which does not implement `Debug` itself.
+#[derive(Clone, Copy)]
+struct TableStyleOption(TableStyle);
+
+impl std::fmt::Debug for TableStyleOption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A sheet name generator, which does not implement `Debug` itself.
+#[derive(Clone)]
+struct SheetNamer(Arc<dyn Fn(usize) -> String + Send + Sync>);
+
+impl SheetNamer {
+    fn name(&self, index: usize) -> String {
+        sanitize_sheet_name(&(self.0)(index))
+    }
+}
+
+impl std::fmt::Debug for SheetNamer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Fn(usize) -> String")
+    }
+}
+
+/// The signature of [`WriteOptionsBuilder::cell_writer`].
+type CustomCellWriteFn =
+    dyn Fn(&mut Worksheet, u32, u16, &dyn Array, usize) -> Result<bool, XlsxError> + Send + Sync;
+
+/// A user cell writer, which does not implement `Debug` itself.
+#[derive(Clone)]
+struct CustomCellWriter(Arc<CustomCellWriteFn>);
+
+impl std::fmt::Debug for CustomCellWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Fn(&mut Worksheet, u32, u16, &dyn Array, usize) -> Result<bool, XlsxError>")
+    }
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            write_header: true,
+            null_text: None,
+            date_format: "yyyy-mm-dd".into(),
+            bool_as_text: false,
+            bool_labels: ("TRUE".into(), "FALSE".into()),
+            freeze_header: true,
+            header_format: Some(Format::new().set_bold()),
+            autofit: false,
+            constant_memory: false,
+            duration_as_seconds: false,
+            binary_encoding: BinaryEncoding::Hex,
+            nan_text: "NaN".into(),
+            infinity_text: "Inf".into(),
+            neg_infinity_text: "-Inf".into(),
+            sheet_per_batch: false,
+            start_row: 0,
+            start_col: 0,
+            autofilter: false,
+            table_style: None,
+            transpose: false,
+            totals_row: false,
+            detect_hyperlinks: false,
+            max_rows: None,
+            skip_rows: 0,
+            strict: false,
+            freeze_columns: 0,
+            numbers_as_text: false,
+            protect: None,
+            decimal_grouping: false,
+            color_scale_numeric: false,
+            sheet_namer: None,
+            row_numbers: false,
+            batch_range: None,
+            emit_schema_sheet: false,
+            header_overrides: Vec::new(),
+            cell_writer: None,
+            title: None,
+            define_name: None,
+            naive_timestamp_tz: None,
+            formula_columns: Vec::new(),
+            merge_runs: Vec::new(),
+            max_cell_chars: MAX_CELL_CHARS,
+            notes_column: None,
+        }
+    }
+}
+
+impl WriteOptions {
+    pub fn builder() -> WriteOptionsBuilder {
+        WriteOptionsBuilder::default()
+    }
+}
+
+/// Builds [`WriteOptions`], starting from the defaults.
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptionsBuilder {
+    options: WriteOptions,
+}
+
+impl WriteOptionsBuilder {
+    /// Whether to write the field names as the first row (default: true).
+    pub fn write_header(mut self, write_header: bool) -> Self {
+        self.options.write_header = write_header;
+        self
+    }
+
+    /// Text written in place of null values, such as `\N` or `NULL`, to tell them apart from empty
+    /// strings, which are always written as they are (default: none, leaving the cell empty).
+    pub fn null_text(mut self, null_text: impl Into<String>) -> Self {
+        self.options.null_text = Some(null_text.into());
+        self
+    }
+
+    /// Excel number format for Date32/Date64 cells (default: `yyyy-mm-dd`).
+    pub fn date_format(mut self, date_format: impl Into<String>) -> Self {
+        self.options.date_format = date_format.into();
+        self
+    }
+
+    /// Whether to write booleans as `TRUE`/`FALSE` text instead of boolean cells (default: false).
+    pub fn bool_as_text(mut self, bool_as_text: bool) -> Self {
+        self.options.bool_as_text = bool_as_text;
+        self
+    }
+
+    /// Text written for true and false values when [`bool_as_text`](Self::bool_as_text) is
+    /// enabled (default: `TRUE` and `FALSE`).
+    pub fn bool_labels(
+        mut self,
+        true_text: impl Into<String>,
+        false_text: impl Into<String>,
+    ) -> Self {
+        self.options.bool_labels = (true_text.into(), false_text.into());
+        self
+    }
+
+    /// Whether to freeze the header row so it stays visible while scrolling (default: true).
+    pub fn freeze_header(mut self, freeze_header: bool) -> Self {
+        self.options.freeze_header = freeze_header;
+        self
+    }
+
+    /// Format of the header cells (default: bold); `None` writes them unstyled.
+    pub fn header_format(mut self, header_format: Option<Format>) -> Self {
+        self.options.header_format = header_format;
+        self
+    }
+
+    /// Whether to fit the column widths to their contents once all rows are written (default: false).
+    pub fn autofit(mut self, autofit: bool) -> Self {
+        self.options.autofit = autofit;
+        self
+    }
+
+    /// Whether to write worksheets in constant-memory mode (default: false).
+    ///
+    /// Each row is flushed to a temporary file once the next row is started, so memory use no
+    /// longer grows with the number of rows, even within a single large batch: batches are
+    /// written row by row, and only the last row of each worksheet is held until the workbook is
+    /// saved. Notes and hyperlinks are still kept in memory. Autofit is not available in this
+    /// mode and is ignored, and [`as_table`](Self::as_table) cannot rewrite the header row.
+    pub fn constant_memory(mut self, constant_memory: bool) -> Self {
+        self.options.constant_memory = constant_memory;
+        self
+    }
+
+    /// Whether to write durations as a number of seconds instead of text such as `1500ms`
+    /// (default: false).
+    pub fn duration_as_seconds(mut self, duration_as_seconds: bool) -> Self {
+        self.options.duration_as_seconds = duration_as_seconds;
+        self
+    }
+
+    /// Text encoding of binary values, unless a column overrides it with a
+    /// [`BINARY_ENCODING_KEY`] field metadata entry (default: [`BinaryEncoding::Hex`]).
+    pub fn binary_encoding(mut self, binary_encoding: BinaryEncoding) -> Self {
+        self.options.binary_encoding = binary_encoding;
+        self
+    }
+
+    /// Text written for NaN floats, which Excel cannot store as numbers (default: `NaN`).
+    pub fn nan_text(mut self, nan_text: impl Into<String>) -> Self {
+        self.options.nan_text = nan_text.into();
+        self
+    }
+
+    /// Text written for positive infinite floats (default: `Inf`).
+    pub fn infinity_text(mut self, infinity_text: impl Into<String>) -> Self {
+        self.options.infinity_text = infinity_text.into();
+        self
+    }
+
+    /// Text written for negative infinite floats (default: `-Inf`).
+    pub fn neg_infinity_text(mut self, neg_infinity_text: impl Into<String>) -> Self {
+        self.options.neg_infinity_text = neg_infinity_text.into();
+        self
+    }
+
+    /// Whether to write each record batch to its own worksheet named `{sheet_name}_{index}`,
+    /// counting from 0 (default: false, writing all batches to one worksheet).
+    pub fn sheet_per_batch(mut self, sheet_per_batch: bool) -> Self {
+        self.options.sheet_per_batch = sheet_per_batch;
+        self
+    }
+
+    /// Zero-based row of the header cell in the top-left corner of each worksheet (default: 0).
+    pub fn start_row(mut self, start_row: u32) -> Self {
+        self.options.start_row = start_row;
+        self
+    }
+
+    /// Zero-based column of the header cell in the top-left corner of each worksheet
+    /// (default: 0).
+    pub fn start_col(mut self, start_col: u16) -> Self {
+        self.options.start_col = start_col;
+        self
+    }
+
+    /// Whether to add filter dropdowns to the header row of each worksheet with data
+    /// (default: false).
+    pub fn autofilter(mut self, autofilter: bool) -> Self {
+        self.options.autofilter = autofilter;
+        self
+    }
+
+    /// Writes the header and data of each worksheet as an Excel table with the given style
+    /// (default: a plain range). The table has its own filter dropdowns, so
+    /// [`autofilter`](Self::autofilter) is ignored, and it cannot contain merged cells, so
+    /// [`merge_runs`](Self::merge_runs) is ignored too.
+    ///
+    /// In [`constant_memory`](Self::constant_memory) mode the table is added after the header
+    /// row has been flushed, so the header keeps the cells written with
+    /// [`header_format`](Self::header_format), and rust_xlsxwriter prints a message to stderr
+    /// for each header cell the table cannot rewrite.
+    pub fn as_table(mut self, style: TableStyle) -> Self {
+        self.options.table_style = Some(TableStyleOption(style));
+        self
+    }
+
+    /// Whether to write each field as a row and each record as a column, with the field names
+    /// down the first column (default: false).
+    ///
+    /// Only practical for small row counts: records beyond the 16,384 columns of a worksheet fail
+    /// with [`Error::TooManyColumns`]. [`autofilter`](Self::autofilter),
+    /// [`as_table`](Self::as_table), [`totals_row`](Self::totals_row),
+    /// [`color_scale_numeric`](Self::color_scale_numeric) and
+    /// [`constant_memory`](Self::constant_memory) are ignored, as every record revisits all rows.
+    /// A [`define_name`](Self::define_name) name covers the fields and records as laid out.
+    pub fn transpose(mut self, transpose: bool) -> Self {
+        self.options.transpose = transpose;
+        self
+    }
+
+    /// Whether to write a row of `SUM` formulas below the data of each worksheet for its numeric
+    /// columns (default: false). A worksheet filled up to the last Excel row has no totals row,
+    /// and it is ignored when [`transpose`](Self::transpose) is enabled.
+    pub fn totals_row(mut self, totals_row: bool) -> Self {
+        self.options.totals_row = totals_row;
+        self
+    }
+
+    /// Whether to write strings starting with `http://` or `https://` as hyperlinks
+    /// (default: false).
+    pub fn detect_hyperlinks(mut self, detect_hyperlinks: bool) -> Self {
+        self.options.detect_hyperlinks = detect_hyperlinks;
+        self
+    }
+
+    /// Maximum number of data rows to write to a worksheet and its continuation sheets
+    /// (default: no limit). Once reached, the remaining batches are not read.
+    pub fn max_rows(mut self, max_rows: u64) -> Self {
+        self.options.max_rows = Some(max_rows);
+        self
+    }
+
+    /// Number of leading data rows to discard before writing, across batch boundaries
+    /// (default: 0). Skipped rows do not count towards [`max_rows`](Self::max_rows).
+    pub fn skip_rows(mut self, skip_rows: u64) -> Self {
+        self.options.skip_rows = skip_rows;
+        self
+    }
+
+    /// Whether to fail with [`Error::UnsupportedType`] on a column of an unsupported data type
+    /// instead of writing a placeholder text (default: false).
+    ///
+    /// The data types are checked against the schema before any batch is written, unless a
+    /// [`cell_writer`](Self::cell_writer) is set, in which case the first value that neither
+    /// writes fails.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.options.strict = strict;
+        self
+    }
+
+    /// Number of leading data columns to freeze so they stay visible while scrolling right,
+    /// along with the header row if it is frozen (default: 0).
+    ///
+    /// With [`WriteOptionsBuilder::transpose`], the leading fields are rows, so that many rows
+    /// are frozen instead.
+    pub fn freeze_columns(mut self, freeze_columns: u16) -> Self {
+        self.options.freeze_columns = freeze_columns;
+        self
+    }
+
+    /// Whether to write integers as text, such as ZIP codes or account numbers that are not
+    /// meant to be calculated with, unless a column overrides it with a [`NUMBER_AS_TEXT_KEY`]
+    /// field metadata entry (default: false).
+    pub fn numbers_as_text(mut self, numbers_as_text: bool) -> Self {
+        self.options.numbers_as_text = numbers_as_text;
+        self
+    }
+
+    /// Protects the worksheets against accidental edits, requiring the password to unprotect them
+    /// if one is given (default: unprotected).
+    ///
+    /// This is worksheet protection only: the file is not encrypted, and its contents can still
+    /// be read by anyone, with or without Excel.
+    pub fn protect(mut self, password: Option<String>) -> Self {
+        self.options.protect = Some(password);
+        self
+    }
+
+    /// Whether to format decimals with a thousands separator, e.g. `#,##0.00` rather than `0.00`
+    /// for a scale of 2 (default: false).
+    ///
+    /// An [`EXCEL_FORMAT_KEY`] field metadata entry still takes precedence for its column.
+    pub fn decimal_grouping(mut self, decimal_grouping: bool) -> Self {
+        self.options.decimal_grouping = decimal_grouping;
+        self
+    }
+
+    /// Whether to shade the data cells of each numeric column with a 3-color scale, from red for
+    /// the lowest values to green for the highest (default: false). It is ignored when
+    /// [`transpose`](Self::transpose) is enabled.
+    pub fn color_scale_numeric(mut self, color_scale_numeric: bool) -> Self {
+        self.options.color_scale_numeric = color_scale_numeric;
+        self
+    }
+
+    /// Names the worksheets of a conversion by calling `namer` with their zero-based index, in
+    /// place of the given sheet name and the [`WriteOptionsBuilder::sheet_per_batch`] and
+    /// continuation suffixes (default: none).
+    ///
+    /// The names are passed through [`sanitize_sheet_name`]. The options are shared by reference,
+    /// so `namer` is an `Fn` rather than an `FnMut`. Partitioned conversions ignore it, as their
+    /// worksheets are named after the partition keys.
+    pub fn sheet_names(mut self, namer: impl Fn(usize) -> String + Send + Sync + 'static) -> Self {
+        self.options.sheet_namer = Some(SheetNamer(Arc::new(namer)));
+        self
+    }
+
+    /// Whether to add a frozen leading column headed [`ROW_NUMBER_HEADER`] with the 1-based number
+    /// of each data row, counted across batches and worksheets (default: false).
+    ///
+    /// The numbers are styled with the header format, and the other columns are shifted right by
+    /// one.
+    pub fn row_numbers(mut self, row_numbers: bool) -> Self {
+        self.options.row_numbers = row_numbers;
+        self
+    }
+
+    /// Zero-based indices of the first and last record batches to write, skipping the batches
+    /// before the range and stopping after it (default: all batches).
+    ///
+    /// Unlike [`WriteOptionsBuilder::skip_rows`] and [`WriteOptionsBuilder::max_rows`], which
+    /// apply to the rows of the written batches, this selects whole batches.
+    pub fn batch_range(mut self, batch_range: RangeInclusive<usize>) -> Self {
+        self.options.batch_range = Some(batch_range);
+        self
+    }
+
+    /// Whether to add a worksheet named [`SCHEMA_SHEET_NAME`] after the data, listing the name,
+    /// data type, nullability and metadata of each field of the schema (default: false).
+    ///
+    /// If the workbook already has a worksheet of that name, ` (2)`, ` (3)`, ... is appended.
+    pub fn emit_schema_sheet(mut self, emit_schema_sheet: bool) -> Self {
+        self.options.emit_schema_sheet = emit_schema_sheet;
+        self
+    }
+
+    /// Names written in the header row in place of the sheet column names, one per column
+    /// (default: none, writing the column names).
+    ///
+    /// The leading [`row_numbers`](Self::row_numbers) column keeps its own header. Writing a
+    /// schema with a different number of columns fails with [`Error::HeaderCountMismatch`].
+    pub fn header_overrides(mut self, header_overrides: Vec<String>) -> Self {
+        self.options.header_overrides = header_overrides;
+        self
+    }
+
+    /// Calls `write` before writing each cell, with the worksheet, the row and column of the cell,
+    /// the column array and the index of the value in it (default: none).
+    ///
+    /// If `write` returns `Ok(true)` it has handled the cell, and nothing else is written to it;
+    /// if it returns `Ok(false)` the cell is written as usual. It is called for null values as
+    /// well, and for the [`row_numbers`](Self::row_numbers) column, but not for the header.
+    pub fn cell_writer(
+        mut self,
+        write: impl Fn(&mut Worksheet, u32, u16, &dyn Array, usize) -> Result<bool, XlsxError>
+        + Send
+        + Sync
+        + 'static,
+    ) -> Self {
+        self.options.cell_writer = Some(CustomCellWriter(Arc::new(write)));
+        self
+    }
+
+    /// Writes a title row above the header, in a large bold cell merged across the sheet columns
+    /// (default: none).
+    ///
+    /// The title is repeated on continuation worksheets, and frozen with the header. When
+    /// [`transpose`](Self::transpose) is enabled it is written to a single cell above the fields.
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.options.title = Some(title.into());
+        self
+    }
+
+    /// Defines a workbook-level name for the header and data cells of the worksheet, for use in
+    /// formulas elsewhere (default: none).
+    ///
+    /// Continuation and [`sheet_per_batch`](Self::sheet_per_batch) worksheets get `name_2`,
+    /// `name_3`, ..., and worksheets without data rows get none. Partitioned and parallel
+    /// conversions ignore it, as their worksheets would share the name.
+    pub fn define_name(mut self, name: impl Into<String>) -> Self {
+        self.options.define_name = Some(name.into());
+        self
+    }
+
+    /// Timezone that timestamps without one are converted to, as those with a timezone are
+    /// converted to theirs (default: none, writing them as UTC).
+    ///
+    /// A [`Tz`] is parsed from an IANA name such as `Asia/Tokyo` or an offset such as `+09:00`.
+    pub fn naive_timestamp_tz(mut self, tz: Tz) -> Self {
+        self.options.naive_timestamp_tz = Some(tz);
+        self
+    }
+
+    /// Sheet column names of string columns whose values starting with `=`, such as `=A1+B1`,
+    /// are written as formulas (default: none).
+    ///
+    /// Other values of these columns are written as text. Naming a column that is not in the
+    /// schema fails with [`Error::ColumnNotFound`].
+    pub fn formula_columns(mut self, formula_columns: Vec<String>) -> Self {
+        self.options.formula_columns = formula_columns;
+        self
+    }
+
+    /// Sheet column names whose runs of equal consecutive values are merged into one cell, for
+    /// grouped reports (default: none).
+    ///
+    /// Runs continue across batches but not across worksheets, and null values are not merged.
+    /// Merging needs earlier rows to be rewritten, so it is ignored in
+    /// [`constant_memory`](Self::constant_memory) mode, and when
+    /// [`transpose`](Self::transpose) is enabled. Excel does not allow merged cells in a table, so
+    /// it is ignored with [`as_table`](Self::as_table) as well. Naming a column that is not in the
+    /// schema fails with [`Error::ColumnNotFound`].
+    pub fn merge_runs(mut self, merge_runs: Vec<String>) -> Self {
+        self.options.merge_runs = merge_runs;
+        self
+    }
+
+    /// The most characters written to a text cell, between 1 and [`MAX_CELL_CHARS`] (default:
+    /// [`MAX_CELL_CHARS`]).
+    ///
+    /// Longer texts made from values, such as strings, the JSON text of list and map columns and
+    /// the encoded text of binary columns, keep their first characters and end with `…`, and are
+    /// counted in [`WriteSummary::truncated_cells`], rather than failing the conversion.
+    pub fn max_cell_chars(mut self, max_cell_chars: usize) -> Self {
+        self.options.max_cell_chars = max_cell_chars.clamp(1, MAX_CELL_CHARS);
+        self
+    }
+
+    /// Writes the values of the `notes` column as notes on the cells of the `target` column
+    /// instead of as a column of their own (default: none).
+    ///
+    /// `notes` is a top-level field, and `target` is a sheet column name, such as `parent.child`
+    /// for a struct field. Null and empty values add no note.
+    pub fn notes_column(mut self, notes: impl Into<String>, target: impl Into<String>) -> Self {
+        self.options.notes_column = Some((notes.into(), target.into()));
+        self
+    }
+
+    pub fn build(self) -> WriteOptions {
+        self.options
+    }
+}
+
+pub fn batch_iter2x<I>(bi: I, book: &mut Workbook, sheet_name: &str) -> Result<(), Error>
 where
     I: Iterator<Item = Result<RecordBatch, arrow::error::ArrowError>>,
 {
-    let worksheet = book.add_worksheet().set_name(sheet_name)?;
+    batch_iter2x_with_options(bi, book, sheet_name, &WriteOptions::default())?;
+    Ok(())
+}
+
+/// Writes the record batches to a new worksheet, as [`batch_iter2x`] does, using the given options.
+pub fn batch_iter2x_with_options<I>(
+    bi: I,
+    book: &mut Workbook,
+    sheet_name: &str,
+    options: &WriteOptions,
+) -> Result<WriteSummary, Error>
+where
+    I: Iterator<Item = Result<RecordBatch, arrow::error::ArrowError>>,
+{
+    batch_iter2x_with_progress(bi, book, sheet_name, options, |_| {})
+}
+
+/// Writes the record batches as [`batch_iter2x_with_options`] does, calling `progress` after each
+/// batch with the number of data rows written so far.
+pub fn batch_iter2x_with_progress<I, F>(
+    bi: I,
+    book: &mut Workbook,
+    sheet_name: &str,
+    options: &WriteOptions,
+    mut progress: F,
+) -> Result<WriteSummary, Error>
+where
+    I: Iterator<Item = Result<RecordBatch, arrow::error::ArrowError>>,
+    F: FnMut(u64),
+{
+    let mut bi = bi.peekable();
+    let mut sheet = SheetWriter::new(book, sheet_name, options)?;
 
-    let mut row_offset = 0;
+    if let Some(Ok(batch)) = bi.peek() {
+        sheet.start(&batch.schema())?;
+    }
 
-    if let Some(batch_result) = bi.next() {
+    for batch_result in bi {
         let batch = batch_result?;
-        let schema = batch.schema();
-        for (col, field) in schema.fields().iter().enumerate() {
-            worksheet.write_string(row_offset, col as u16, field.name())?;
+        sheet.write_batch(book, &batch)?;
+        progress(sheet.rows_written);
+        if sheet.is_full() {
+            break;
+        }
+    }
+
+    sheet.finish(book)
+}
+
+/// Writes the record batches of a stream as [`batch_iter2x_with_options`] does.
+///
+/// Each batch is written as soon as it arrives, in the order of the stream, so only one batch is
+/// held at a time. Writing itself does not await; the workbook is borrowed until the stream ends.
+#[cfg(feature = "async")]
+pub async fn batch_stream2x<S>(
+    stream: S,
+    book: &mut Workbook,
+    sheet_name: &str,
+    options: &WriteOptions,
+) -> Result<WriteSummary, Error>
+where
+    S: futures::Stream<Item = Result<RecordBatch, arrow::error::ArrowError>>,
+{
+    use futures::StreamExt;
+
+    let mut stream = std::pin::pin!(stream);
+    let mut sheet = SheetWriter::new(book, sheet_name, options)?;
+
+    let mut started = false;
+    while let Some(batch_result) = stream.next().await {
+        let batch = batch_result?;
+        if !started {
+            sheet.start(&batch.schema())?;
+            started = true;
+        }
+        sheet.write_batch(book, &batch)?;
+        if sheet.is_full() {
+            break;
         }
-        row_offset += 1;
+    }
+
+    sheet.finish(book)
+}
 
-        write_batch(worksheet, &batch, &mut row_offset)?;
+/// Writes the record batches to a new worksheet, taking the header from the given schema.
+///
+/// Unlike [`batch_iter2x_with_options`], the header row is written even if there are no batches.
+pub fn batch_iter2x_with_schema<I>(
+    bi: I,
+    schema: &Schema,
+    book: &mut Workbook,
+    sheet_name: &str,
+    options: &WriteOptions,
+) -> Result<WriteSummary, Error>
+where
+    I: Iterator<Item = Result<RecordBatch, arrow::error::ArrowError>>,
+{
+    let mut sheet = SheetWriter::new(book, sheet_name, options)?;
+    sheet.start(schema)?;
 
-        for batch_result in bi {
-            let batch = batch_result?;
-            write_batch(worksheet, &batch, &mut row_offset)?;
+    for batch_result in bi {
+        let batch = batch_result?;
+        sheet.write_batch(book, &batch)?;
+        if sheet.is_full() {
+            break;
         }
     }
 
+    sheet.finish(book)
+}
+
+/// Writes the rows of the record batches for which `predicate` returns `true` to a new
+/// worksheet, as [`batch_iter2x`] does.
+///
+/// The predicate is called with each batch and the index of a row in it.
+pub fn batch_iter2x_filtered<I, P>(
+    bi: I,
+    book: &mut Workbook,
+    sheet_name: &str,
+    predicate: P,
+) -> Result<(), Error>
+where
+    I: Iterator<Item = Result<RecordBatch, arrow::error::ArrowError>>,
+    P: Fn(&RecordBatch, usize) -> bool,
+{
+    batch_iter2x_filtered_with_options(bi, book, sheet_name, predicate, &WriteOptions::default())?;
+    Ok(())
+}
+
+/// Writes the rows as [`batch_iter2x_filtered`] does, using the given options.
+///
+/// Rows are dropped before they are written, so the summary, the row numbers and the worksheet
+/// continuation only count the rows that match. Options such as
+/// [`WriteOptionsBuilder::skip_rows`] apply to the matching rows as well.
+pub fn batch_iter2x_filtered_with_options<I, P>(
+    bi: I,
+    book: &mut Workbook,
+    sheet_name: &str,
+    predicate: P,
+    options: &WriteOptions,
+) -> Result<WriteSummary, Error>
+where
+    I: Iterator<Item = Result<RecordBatch, arrow::error::ArrowError>>,
+    P: Fn(&RecordBatch, usize) -> bool,
+{
+    let filtered = bi.map(|batch_result| {
+        let batch = batch_result?;
+        let mask: BooleanArray = (0..batch.num_rows())
+            .map(|row| Some(predicate(&batch, row)))
+            .collect();
+        filter_record_batch(&batch, &mask)
+    });
+    batch_iter2x_with_options(filtered, book, sheet_name, options)
+}
+
+/// Writes the record batches of the reader to a new worksheet.
+///
+/// The header is taken from the schema of the reader, so it is written even if the reader has no
+/// batches.
+pub fn reader2x<R>(reader: R, book: &mut Workbook, sheet_name: &str) -> Result<(), Error>
+where
+    R: RecordBatchReader,
+{
+    reader2x_with_options(reader, book, sheet_name, &WriteOptions::default())?;
     Ok(())
 }
 
-macro_rules! write_primitive_number {
-    ($worksheet:expr, $column:expr, $row:expr, $row_offset:expr, $col:expr, $type:ty) => {{
-        let array = as_primitive_array::<$type>($column);
-        if !array.is_null($row) {
-            let value = array.value($row);
-            $worksheet.write_number($row_offset, $col as u16, value as f64)?;
+/// Writes the record batches of the reader as [`reader2x`] does, using the given options.
+pub fn reader2x_with_options<R>(
+    reader: R,
+    book: &mut Workbook,
+    sheet_name: &str,
+    options: &WriteOptions,
+) -> Result<WriteSummary, Error>
+where
+    R: RecordBatchReader,
+{
+    let schema = reader.schema();
+    batch_iter2x_with_schema(reader, &schema, book, sheet_name, options)
+}
+
+/// Writes each reader to a worksheet of its own, as [`reader2x_with_options`] does, rendering
+/// the worksheets in parallel on the rayon thread pool.
+///
+/// A `Workbook` cannot be shared across threads, so each reader is written to a workbook of its
+/// own, whose worksheets are then moved to `book` in the order of `readers`, whichever finishes
+/// first. [`WriteOptionsBuilder::constant_memory`] is ignored, as such worksheets cannot be moved
+/// between workbooks. Returns the summary of each reader, in the same order.
+#[cfg(feature = "parallel")]
+pub fn readers2x_parallel<R>(
+    readers: Vec<(String, R)>,
+    book: &mut Workbook,
+    options: &WriteOptions,
+) -> Result<Vec<WriteSummary>, Error>
+where
+    R: RecordBatchReader + Send,
+{
+    use rayon::prelude::*;
+
+    // Schema sheets are added to `book` itself, so that their names do not clash.
+    let sheet_options = WriteOptions {
+        constant_memory: false,
+        emit_schema_sheet: false,
+        define_name: None,
+        ..options.clone()
+    };
+    let rendered = readers
+        .into_par_iter()
+        .map(|(sheet_name, reader)| {
+            let schema = reader.schema();
+            let mut own_book = Workbook::new();
+            let summary =
+                reader2x_with_options(reader, &mut own_book, &sheet_name, &sheet_options)?;
+            Ok((std::mem::take(own_book.worksheets_mut()), schema, summary))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let mut summaries = Vec::with_capacity(rendered.len());
+    for (worksheets, schema, mut summary) in rendered {
+        for worksheet in worksheets {
+            book.push_worksheet(worksheet);
         }
-    }};
+        if options.emit_schema_sheet {
+            push_schema_sheet(book, &schema, options)?;
+            summary.sheets_written += 1;
+        }
+        summaries.push(summary);
+    }
+    Ok(summaries)
 }
 
-fn write_batch(
-    worksheet: &mut rust_xlsxwriter::Worksheet,
-    batch: &RecordBatch,
-    row_offset: &mut u32,
-) -> Result<(), XlsxError> {
-    for row in 0..batch.num_rows() {
-        for col in 0..batch.num_columns() {
-            let column = batch.column(col);
-            let data_type = column.data_type();
-
-            match data_type {
-                DataType::Utf8 => {
-                    let array = as_string_array(column);
-                    if !array.is_null(row) {
-                        let value = array.value(row);
-                        worksheet.write_string(*row_offset, col as u16, value)?;
-                    }
-                }
-                DataType::Int8 => {
-                    write_primitive_number!(worksheet, column, row, *row_offset, col, Int8Type)
-                }
-                DataType::Int16 => {
-                    write_primitive_number!(worksheet, column, row, *row_offset, col, Int16Type)
-                }
-                DataType::Int32 => {
-                    write_primitive_number!(worksheet, column, row, *row_offset, col, Int32Type)
-                }
-                DataType::Int64 => {
-                    write_primitive_number!(worksheet, column, row, *row_offset, col, Int64Type)
-                }
-                DataType::UInt8 => {
-                    write_primitive_number!(worksheet, column, row, *row_offset, col, UInt8Type)
-                }
-                DataType::UInt16 => {
-                    write_primitive_number!(worksheet, column, row, *row_offset, col, UInt16Type)
-                }
-                DataType::UInt32 => {
-                    write_primitive_number!(worksheet, column, row, *row_offset, col, UInt32Type)
-                }
-                DataType::UInt64 => {
-                    write_primitive_number!(worksheet, column, row, *row_offset, col, UInt64Type)
-                }
-                DataType::Float16 => {
-                    let array = as_primitive_array::<Float16Type>(column);
-                    if !array.is_null(row) {
-                        let value = array.value(row);
-                        worksheet.write_number(*row_offset, col as u16, value.to_f64())?;
-                    }
-                }
-                DataType::Float32 => {
-                    write_primitive_number!(worksheet, column, row, *row_offset, col, Float32Type)
-                }
-                DataType::Float64 => {
-                    write_primitive_number!(worksheet, column, row, *row_offset, col, Float64Type)
-                }
-                DataType::Boolean => {
-                    let array = as_boolean_array(column);
-                    if !array.is_null(row) {
-                        let value = array.value(row);
-                        worksheet.write_boolean(*row_offset, col as u16, value)?;
-                    }
+/// Writes a single record batch to a new workbook with one worksheet.
+pub fn batch_to_workbook(batch: &RecordBatch, sheet_name: &str) -> Result<Workbook, Error> {
+    let mut book = Workbook::new();
+    let options = WriteOptions::default();
+    let mut sheet = SheetWriter::new(&mut book, sheet_name, &options)?;
+    sheet.start(&batch.schema())?;
+    sheet.write_batch(&mut book, batch)?;
+    sheet.finish(&mut book)?;
+    Ok(book)
+}
+
+/// Writes the record batches as JSON lines, one object per row keyed by the sheet column names,
+/// and returns the number of rows written.
+///
+/// Struct fields are flattened into `parent.child` keys as they are for worksheets. Values are
+/// formatted as in list and union cells: nulls and non-finite floats become `null`, lists and
+/// maps become arrays and objects, and other types such as dates, timestamps and decimals become
+/// their display string, such as `"2024-01-31"` or `"12.50"`.
+pub fn batch_iter2json<I, W>(bi: I, mut w: W) -> Result<u64, Error>
+where
+    I: Iterator<Item = Result<RecordBatch, arrow::error::ArrowError>>,
+    W: Write,
+{
+    let mut rows = 0;
+    let mut line = String::new();
+    for batch in bi {
+        let batch = batch?;
+        let keys: Vec<String> = leaf_fields(batch.schema().fields())
+            .into_iter()
+            .map(|(name, _)| {
+                let mut key = String::new();
+                push_json_string(&mut key, &name);
+                key
+            })
+            .collect();
+        let columns = leaf_columns(&batch)?;
+        for row in 0..batch.num_rows() {
+            line.clear();
+            line.push('{');
+            for (index, (key, column)) in keys.iter().zip(&columns).enumerate() {
+                if index > 0 {
+                    line.push(',');
                 }
-                DataType::Date32 => {
-                    let array = as_primitive_array::<Date32Type>(column);
-                    if !array.is_null(row) {
-                        let value = array.value(row);
-                        if let Some(date) = NaiveDate::from_epoch_days(value) {
-                            worksheet.write_datetime(*row_offset, col as u16, date)?;
-                        }
-                    }
+                line.push_str(key);
+                line.push(':');
+                if column.is_null(row) {
+                    line.push_str("null");
+                } else {
+                    push_json_value(&mut line, column.array.as_ref(), row);
                 }
-                DataType::Date64 => {
-                    let array = as_primitive_array::<Date64Type>(column);
-                    if !array.is_null(row) {
-                        let value = array.value(row);
-                        if let Some(datetime_utc) = DateTime::<Utc>::from_timestamp_millis(value) {
-                            worksheet.write_datetime(
-                                *row_offset,
-                                col as u16,
-                                datetime_utc.naive_utc(),
-                            )?;
-                        }
-                    }
+            }
+            line.push_str("}\n");
+            w.write_all(line.as_bytes())?;
+        }
+        rows += batch.num_rows() as u64;
+    }
+    w.flush()?;
+    Ok(rows)
+}
+
+/// Writes the record batches as CSV with a header row of the sheet column names, and returns
+/// the number of data rows written.
+///
+/// Struct fields are flattened into `parent.child` columns as they are for worksheets. Values are
+/// written as their display string, such as `2024-01-31` for a date, and nulls as empty fields.
+/// Fields containing the delimiter, a quote or a line break are quoted, with quotes doubled.
+pub fn batch_iter2csv<I, W>(bi: I, mut w: W, delimiter: u8) -> Result<u64, Error>
+where
+    I: Iterator<Item = Result<RecordBatch, arrow::error::ArrowError>>,
+    W: Write,
+{
+    let delimiter = char::from(delimiter);
+    let options = FormatOptions::default();
+    let mut rows = 0;
+    let mut header_written = false;
+    let mut line = String::new();
+    for batch in bi {
+        let batch = batch?;
+        if !header_written {
+            line.clear();
+            for (index, (name, _)) in leaf_fields(batch.schema().fields()).iter().enumerate() {
+                if index > 0 {
+                    line.push(delimiter);
                 }
-                DataType::Time32(unit) => match unit {
-                    TimeUnit::Second => {
-                        let array = as_primitive_array::<Time32SecondType>(column);
-                        if !array.is_null(row) {
-                            let value = array.value(row);
-                            worksheet.write_number(
-                                *row_offset,
-                                col as u16,
-                                value as f64 / 86400.0,
-                            )?;
-                        }
-                    }
-                    TimeUnit::Millisecond => {
-                        let array = as_primitive_array::<Time32MillisecondType>(column);
-                        if !array.is_null(row) {
-                            let value = array.value(row);
-                            worksheet.write_number(
-                                *row_offset,
-                                col as u16,
-                                value as f64 / 86_400_000.0,
-                            )?;
-                        }
-                    }
-                    _ => {}
-                },
-                DataType::Time64(unit) => match unit {
-                    TimeUnit::Microsecond => {
-                        let array = as_primitive_array::<Time64MicrosecondType>(column);
-                        if !array.is_null(row) {
-                            let value = array.value(row);
-                            worksheet.write_number(
-                                *row_offset,
-                                col as u16,
-                                value as f64 / 86_400_000_000.0,
-                            )?;
-                        }
-                    }
-                    TimeUnit::Nanosecond => {
-                        let array = as_primitive_array::<Time64NanosecondType>(column);
-                        if !array.is_null(row) {
-                            let value = array.value(row);
-                            worksheet.write_number(
-                                *row_offset,
-                                col as u16,
-                                value as f64 / 86_400_000_000_000.0,
-                            )?;
-                        }
-                    }
-                    _ => {}
-                },
-                DataType::Timestamp(unit, _) => {
-                    let s = match unit {
-                        TimeUnit::Second => {
-                            let array =
-                                as_primitive_array::<arrow::datatypes::TimestampSecondType>(column);
-                            if !array.is_null(row) {
-                                format!("{}", array.value(row))
-                            } else {
-                                String::new()
-                            }
-                        }
-                        TimeUnit::Millisecond => {
-                            let array = as_primitive_array::<
-                                arrow::datatypes::TimestampMillisecondType,
-                            >(column);
-                            if !array.is_null(row) {
-                                format!("{}", array.value(row))
-                            } else {
-                                String::new()
-                            }
-                        }
-                        TimeUnit::Microsecond => {
-                            let array = as_primitive_array::<
-                                arrow::datatypes::TimestampMicrosecondType,
-                            >(column);
-                            if !array.is_null(row) {
-                                format!("{}", array.value(row))
-                            } else {
-                                String::new()
-                            }
-                        }
-                        TimeUnit::Nanosecond => {
-                            let array = as_primitive_array::<
-                                arrow::datatypes::TimestampNanosecondType,
-                            >(column);
-                            if !array.is_null(row) {
-                                format!("{}", array.value(row))
-                            } else {
-                                String::new()
-                            }
-                        }
-                    };
-                    worksheet.write_string(*row_offset, col as u16, s)?;
+                push_csv_field(&mut line, name, delimiter);
+            }
+            line.push('\n');
+            w.write_all(line.as_bytes())?;
+            header_written = true;
+        }
+        let columns = leaf_columns(&batch)?;
+        let formatters = columns
+            .iter()
+            .map(|column| ArrayFormatter::try_new(column.array.as_ref(), &options))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut value = String::new();
+        for row in 0..batch.num_rows() {
+            line.clear();
+            for (index, (formatter, column)) in formatters.iter().zip(&columns).enumerate() {
+                if index > 0 {
+                    line.push(delimiter);
                 }
-                _ => {
-                    worksheet.write_string(
-                        *row_offset,
-                        col as u16,
-                        format!("unsupported data type: {:?}", data_type),
-                    )?;
+                if column.is_null(row) {
+                    continue;
                 }
+                value.clear();
+                formatter.value(row).write(&mut value)?;
+                push_csv_field(&mut line, &value, delimiter);
             }
+            line.push('\n');
+            w.write_all(line.as_bytes())?;
         }
-        *row_offset += 1;
+        rows += batch.num_rows() as u64;
+    }
+    w.flush()?;
+    Ok(rows)
+}
+
+/// Appends a CSV field, quoted if it contains the delimiter, a quote or a line break.
+fn push_csv_field(out: &mut String, value: &str, delimiter: char) {
+    if !value.contains([delimiter, '"', '\n', '\r']) {
+        out.push_str(value);
+        return;
     }
+    out.push('"');
+    out.push_str(&value.replace('"', "\"\""));
+    out.push('"');
+}
+
+/// The worksheet name used by [`batch_iter2x_partitioned`] for rows whose partition value is
+/// null.
+pub const NULL_PARTITION_SHEET_NAME: &str = "(null)";
+
+/// Writes the record batches to one worksheet per distinct value of the `column_name` column.
+pub fn batch_iter2x_partitioned<I>(
+    bi: I,
+    book: &mut Workbook,
+    column_name: &str,
+) -> Result<(), Error>
+where
+    I: Iterator<Item = Result<RecordBatch, arrow::error::ArrowError>>,
+{
+    batch_iter2x_partitioned_with_options(bi, book, column_name, &WriteOptions::default())?;
     Ok(())
 }
+
+/// Writes the record batches as [`batch_iter2x_partitioned`] does, using the given options.
+///
+/// Each worksheet is named after the partition value, created when the value is first seen, and
+/// holds the remaining columns. Values are written as text and sanitized like any other sheet
+/// name, so values that end up with the same name share a worksheet. Rows with a null value go
+/// to [`NULL_PARTITION_SHEET_NAME`].
+pub fn batch_iter2x_partitioned_with_options<I>(
+    bi: I,
+    book: &mut Workbook,
+    column_name: &str,
+    options: &WriteOptions,
+) -> Result<WriteSummary, Error>
+where
+    I: Iterator<Item = Result<RecordBatch, arrow::error::ArrowError>>,
+{
+    // The batch range applies to the input batches here rather than to the partitions.
+    let batch_range = options.batch_range.clone();
+    let emit_schema_sheet = options.emit_schema_sheet;
+    let options = WriteOptions {
+        sheet_namer: None,
+        batch_range: None,
+        emit_schema_sheet: false,
+        define_name: None,
+        ..options.clone()
+    };
+    let options = &options;
+    let mut first_schema: Option<SchemaRef> = None;
+    let mut sheets: Vec<SheetWriter> = Vec::new();
+    // Excel compares sheet names case-insensitively.
+    let mut sheet_indices: HashMap<String, usize> = HashMap::new();
+
+    for (index, batch_result) in bi.enumerate() {
+        if batch_range
+            .as_ref()
+            .is_some_and(|range| index > *range.end())
+        {
+            break;
+        }
+        let batch = batch_result?;
+        if batch_range
+            .as_ref()
+            .is_some_and(|range| !range.contains(&index))
+        {
+            continue;
+        }
+        let schema = batch.schema();
+        first_schema.get_or_insert_with(|| schema.clone());
+        let Some((key_index, _)) = schema.column_with_name(column_name) else {
+            return Err(column_not_found(column_name, schema.fields()));
+        };
+        let keys = cast(batch.column(key_index), &DataType::Utf8)?;
+        let keys = keys.as_string::<i32>();
+        let value_indices: Vec<usize> = (0..batch.num_columns())
+            .filter(|&i| i != key_index)
+            .collect();
+        let values = batch.project(&value_indices)?;
+
+        let mut sheet_rows: Vec<Vec<u32>> = vec![Vec::new(); sheets.len()];
+        for row in 0..batch.num_rows() {
+            let sheet_name = if keys.is_null(row) {
+                NULL_PARTITION_SHEET_NAME.into()
+            } else {
+                sanitize_sheet_name(keys.value(row))
+            };
+            let index = match sheet_indices.get(&sheet_name.to_lowercase()) {
+                Some(&index) => index,
+                None => {
+                    let mut sheet = SheetWriter::new(book, &sheet_name, options)?;
+                    sheet.start(&values.schema())?;
+                    sheets.push(sheet);
+                    sheet_rows.push(Vec::new());
+                    sheet_indices.insert(sheet_name.to_lowercase(), sheets.len() - 1);
+                    sheets.len() - 1
+                }
+            };
+            sheet_rows[index].push(row as u32);
+        }
+
+        for (sheet, rows) in sheets.iter_mut().zip(sheet_rows) {
+            if !rows.is_empty() {
+                let rows = take_record_batch(&values, &UInt32Array::from(rows))?;
+                sheet.write_batch(book, &rows)?;
+            }
+        }
+    }
+
+    let mut summary = WriteSummary::default();
+    for sheet in sheets {
+        let sheet_summary = sheet.finish(book)?;
+        summary.rows_written += sheet_summary.rows_written;
+        summary.sheets_written += sheet_summary.sheets_written;
+        summary.columns = sheet_summary.columns;
+        summary.unsupported_cells += sheet_summary.unsupported_cells;
+        summary.truncated_cells += sheet_summary.truncated_cells;
+        if summary.column_stats.is_empty() {
+            summary.column_stats = sheet_summary.column_stats;
+        } else {
+            for (stats, sheet_stats) in summary
+                .column_stats
+                .iter_mut()
+                .zip(&sheet_summary.column_stats)
+            {
+                stats.merge(sheet_stats);
+            }
+        }
+    }
+    if emit_schema_sheet && let Some(schema) = &first_schema {
+        push_schema_sheet(book, schema, options)?;
+        summary.sheets_written += 1;
+    }
+    Ok(summary)
+}
+
+/// Saves the workbook to any writer, such as stdout or an HTTP response body.
+///
+/// The xlsx container is assembled in memory first because it needs random access, which
+/// a plain writer does not provide.
+pub fn save_to_writer<W: Write>(book: &mut Workbook, mut writer: W) -> Result<(), Error> {
+    let buf = book.save_to_buffer()?;
+    writer.write_all(&buf)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// The magic bytes at the start of an Arrow IPC file; streams do not have them.
+const ARROW_FILE_MAGIC: &[u8] = b"ARROW1";
+
+/// Returns true if the input starts with the Arrow IPC file magic rather than a stream message.
+///
+/// Only the buffered bytes are inspected, so nothing is consumed from the reader. A pipe, socket
+/// or decoder may buffer fewer bytes than the magic on its first read, so such input should be
+/// wrapped with [`buffer_magic`] first.
+pub fn is_ipc_file<R: BufRead>(reader: &mut R) -> std::io::Result<bool> {
+    let buf = reader.fill_buf()?;
+    Ok(buf.starts_with(ARROW_FILE_MAGIC))
+}
+
+/// Reads the input until the length of the Arrow IPC file magic is read or the input ends, and
+/// returns a reader of the whole input whose first [`BufRead::fill_buf`] returns those bytes.
+pub fn buffer_magic<R: Read>(
+    mut reader: R,
+) -> std::io::Result<BufReader<Chain<Cursor<Vec<u8>>, R>>> {
+    let mut magic = Vec::with_capacity(ARROW_FILE_MAGIC.len());
+    (&mut reader)
+        .take(ARROW_FILE_MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+    Ok(BufReader::new(Cursor::new(magic).chain(reader)))
+}
+
+/// Reads Arrow IPC streams written back to back, such as by several writers to one pipe, as one.
+///
+/// After the end of each stream, another one is read from the remaining bytes until the input
+/// ends. The schema is that of the first stream. Batches of later streams are yielded as they are,
+/// so writing them to the same worksheet fails with [`Error::SchemaMismatch`] if their schema
+/// differs.
+pub struct ConcatStreamReader<R: BufRead> {
+    schema: SchemaRef,
+    stream: StreamReader<Handoff<R>>,
+}
+
+impl<R: BufRead> ConcatStreamReader<R> {
+    /// Reads the schema of the first stream.
+    pub fn try_new(reader: R) -> Result<Self, arrow::error::ArrowError> {
+        let stream = StreamReader::try_new(Handoff(Some(reader)), None)?;
+        Ok(Self {
+            schema: stream.schema(),
+            stream,
+        })
+    }
+
+    /// Starts reading the next stream, returning `false` if the input has ended.
+    fn next_stream(&mut self) -> Result<bool, arrow::error::ArrowError> {
+        let Some(mut reader) = self.stream.get_mut().0.take() else {
+            return Ok(false);
+        };
+        if reader.fill_buf()?.is_empty() {
+            return Ok(false);
+        }
+        self.stream = StreamReader::try_new(Handoff(Some(reader)), None)?;
+        Ok(true)
+    }
+}
+
+impl<R: BufRead> Iterator for ConcatStreamReader<R> {
+    type Item = Result<RecordBatch, arrow::error::ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(batch) = self.stream.next() {
+                return Some(batch);
+            }
+            match self.next_stream() {
+                Ok(true) => continue,
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+impl<R: BufRead> RecordBatchReader for ConcatStreamReader<R> {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+/// Lends a reader to a [`StreamReader`] so that it can be taken back once the stream ends.
+struct Handoff<R>(Option<R>);
+
+impl<R: Read> Read for Handoff<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match &mut self.0 {
+            Some(reader) => reader.read(buf),
+            None => Ok(0),
+        }
+    }
+}
+
+/// What was written by a conversion.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WriteSummary {
+    /// Number of data rows written, excluding header rows.
+    pub rows_written: u64,
+    /// Number of worksheets added to the workbook.
+    pub sheets_written: u32,
+    /// Number of sheet columns, after flattening struct fields.
+    pub columns: usize,
+    /// Number of cells written as an `unsupported data type` placeholder.
+    pub unsupported_cells: u64,
+    /// Number of text cells shortened to [`WriteOptionsBuilder::max_cell_chars`] characters.
+    pub truncated_cells: u64,
+    /// Statistics of the values written to each sheet column, in order.
+    pub column_stats: Vec<ColumnStats>,
+}
+
+/// Statistics of the values written to a sheet column.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ColumnStats {
+    /// Number of null values.
+    pub nulls: u64,
+    /// Smallest value of a numeric column, ignoring NaN, or `None` if it has no such value.
+    pub min: Option<f64>,
+    /// Largest value of a numeric column, ignoring NaN, or `None` if it has no such value.
+    pub max: Option<f64>,
+}
+
+impl ColumnStats {
+    /// Adds the values of a column to the statistics.
+    fn update(&mut self, column: &LeafColumn) -> Result<(), arrow::error::ArrowError> {
+        self.nulls += column.logical_null_count() as u64;
+        if !column.array.data_type().is_numeric() {
+            return Ok(());
+        }
+        let values = cast(&column.array, &DataType::Float64)?;
+        for value in values.as_primitive::<Float64Type>().iter().flatten() {
+            if value.is_nan() {
+                continue;
+            }
+            self.min = Some(self.min.map_or(value, |min| min.min(value)));
+            self.max = Some(self.max.map_or(value, |max| max.max(value)));
+        }
+        Ok(())
+    }
+
+    /// Combines the statistics of the same column on another worksheet.
+    fn merge(&mut self, other: &ColumnStats) {
+        self.nulls += other.nulls;
+        self.min = match (self.min, other.min) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.max = match (self.max, other.max) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+    }
+}
+
+/// The maximum number of rows in an Excel worksheet.
+pub const MAX_ROWS: u32 = 1_048_576;
+
+/// The maximum number of columns in an Excel worksheet.
+pub const MAX_COLS: usize = 16_384;
+
+/// The maximum number of characters in an Excel worksheet name.
+pub const MAX_SHEET_NAME_CHARS: usize = 31;
+
+/// Makes a worksheet name acceptable to Excel.
+///
+/// The characters `[]:*?/\` and leading or trailing apostrophes are replaced with `_`, the name
+/// is truncated to [`MAX_SHEET_NAME_CHARS`] characters, and an empty name becomes `Sheet1`.
+pub fn sanitize_sheet_name(name: &str) -> String {
+    let mut sanitized: Vec<char> = name
+        .chars()
+        .take(MAX_SHEET_NAME_CHARS)
+        .map(|c| match c {
+            '[' | ']' | ':' | '*' | '?' | '/' | '\\' => '_',
+            c => c,
+        })
+        .collect();
+    if sanitized.first() == Some(&'\'') {
+        sanitized[0] = '_';
+    }
+    if let Some(last) = sanitized.last_mut()
+        && *last == '\''
+    {
+        *last = '_';
+    }
+    if sanitized.is_empty() {
+        return "Sheet1".into();
+    }
+    sanitized.into_iter().collect()
+}
+
+/// The column of [`WriteOptionsBuilder::notes_column`] in the schema of a [`SheetWriter`].
+struct NotesColumn {
+    /// The index of the notes field in the schema.
+    index: usize,
+    /// The indices of the other fields, which are written as sheet columns.
+    others: Vec<usize>,
+    /// The index of the sheet column the notes are added to.
+    target: usize,
+}
+
+/// The current run of equal values in a [`WriteOptionsBuilder::merge_runs`] column.
+struct MergeRun {
+    /// The index of the sheet column.
+    index: usize,
+    /// The first value of the run, as a single-element array to write it again after merging.
+    first: Option<ArrayRef>,
+    /// The worksheet row of the first value.
+    first_row: u32,
+    /// The number of rows in the run.
+    len: u32,
+    /// The last value written, as a single-element array, unless it is null or on a previous
+    /// worksheet.
+    last: Option<ArrayRef>,
+}
+
+impl MergeRun {
+    fn new(index: usize) -> Self {
+        Self {
+            index,
+            first: None,
+            first_row: 0,
+            len: 0,
+            last: None,
+        }
+    }
+
+    /// Extends the run with the given rows of the column, written downward from `row_offset`,
+    /// merging each run that ends.
+    fn track(
+        &mut self,
+        worksheet: &mut Worksheet,
+        column: &LeafColumn,
+        rows: Range<usize>,
+        row_offset: u32,
+        spec: &ColumnSpec,
+        options: &WriteOptions,
+    ) -> Result<(), Error> {
+        let array = column.array.as_ref();
+        let within = make_comparator(array, array, SortOptions::default())?;
+        let after_last = match &self.last {
+            Some(last) => Some(make_comparator(
+                last.as_ref(),
+                array,
+                SortOptions::default(),
+            )?),
+            None => None,
+        };
+        for (row_offset, row) in (row_offset..).zip(rows.clone()) {
+            let same = !column.is_null(row)
+                && if row > rows.start {
+                    !column.is_null(row - 1) && within(row - 1, row).is_eq()
+                } else {
+                    after_last
+                        .as_ref()
+                        .is_some_and(|compare| compare(0, row).is_eq())
+                };
+            if same {
+                self.len += 1;
+            } else {
+                self.merge(worksheet, spec, options)?;
+                self.first = Some(array.slice(row, 1));
+                self.first_row = row_offset;
+                self.len = 1;
+            }
+        }
+        self.last = rows
+            .last()
+            .filter(|&row| !column.is_null(row))
+            .map(|row| array.slice(row, 1));
+        Ok(())
+    }
+
+    /// Merges the cells of the run if it has more than one row, and writes its value again, as
+    /// merging leaves only a string.
+    fn merge(
+        &mut self,
+        worksheet: &mut Worksheet,
+        spec: &ColumnSpec,
+        options: &WriteOptions,
+    ) -> Result<(), Error> {
+        if self.len > 1
+            && let Some(first) = &self.first
+        {
+            let col = options.start_col + self.index as u16;
+            let last_row = self.first_row + self.len - 1;
+            let default = Format::default();
+            let format = spec.format.as_deref().unwrap_or(&default);
+            worksheet.merge_range(self.first_row, col, last_row, col, "", format)?;
+            // The first value was counted by `truncate_cell_text` when it was written before,
+            // so the count of the rewrite is dropped.
+            let truncated = spec.take_truncated();
+            cell_writer(first.as_ref(), None, spec, options)(worksheet, self.first_row, col, 0)?;
+            spec.take_truncated();
+            spec.truncated.fetch_add(truncated, Ordering::Relaxed);
+        }
+        self.len = 0;
+        Ok(())
+    }
+}
+
+/// Returns the note at the given row, or `None` if it is null or empty.
+fn note_text(notes: &StringArray, row: usize) -> Option<&str> {
+    if notes.is_null(row) {
+        return None;
+    }
+    Some(notes.value(row)).filter(|note| !note.is_empty())
+}
+
+fn column_not_found(name: &str, fields: &Fields) -> Error {
+    Error::ColumnNotFound {
+        name: name.into(),
+        available: fields.iter().map(|f| f.name().clone()).collect(),
+    }
+}
+
+/// The header of the [`WriteOptionsBuilder::row_numbers`] column.
+pub const ROW_NUMBER_HEADER: &str = "#";
+
+/// Appends a suffix to a sheet name, truncating the name so the result fits in
+/// [`MAX_SHEET_NAME_CHARS`].
+pub fn suffixed_sheet_name(sheet_name: &str, suffix: &str) -> String {
+    let base: String = sheet_name
+        .chars()
+        .take(MAX_SHEET_NAME_CHARS - suffix.len())
+        .collect();
+    format!("{base}{suffix}")
+}
+
+/// Writes rows to a worksheet, continuing on a new worksheet when [`MAX_ROWS`] is reached.
+///
+/// The continuation sheets are named `{sheet_name} (2)`, `{sheet_name} (3)`, ... and start with
+/// the header row again. With [`WriteOptionsBuilder::sheet_per_batch`], each batch starts a new
+/// worksheet as well. Worksheets are added to the workbook as they are completed.
+struct SheetWriter<'a> {
+    options: &'a WriteOptions,
+    sheet_name: String,
+    /// The name of the current worksheet, without the continuation suffix.
+    base_name: String,
+    /// The continuation number of the current worksheet, starting at 1.
+    part: u32,
+    batches: usize,
+    /// The number of batches passed to `write_batch`, including those outside the batch range.
+    batches_read: usize,
+    /// The number of names defined by [`WriteOptionsBuilder::define_name`].
+    defined_names: u32,
+    schema: Option<SchemaRef>,
+    header: Vec<String>,
+    specs: Arc<[ColumnSpec]>,
+    formats: FormatCache,
+    notes: Option<NotesColumn>,
+    runs: Vec<MergeRun>,
+    /// The statistics of each sheet column.
+    stats: Vec<ColumnStats>,
+    worksheet: Worksheet,
+    sheets: u32,
+    /// The row the next data row is written to. It never passes [`MAX_ROWS`], as `write_batch`
+    /// continues on a new worksheet once it is reached.
+    row_offset: u32,
+    /// The number of records written to the current worksheet when transposing.
+    transposed_records: usize,
+    columns: usize,
+    rows_written: u64,
+    rows_skipped: u64,
+    unsupported_cells: u64,
+    truncated_cells: u64,
+}
+
+impl<'a> SheetWriter<'a> {
+    fn new(
+        book: &mut Workbook,
+        sheet_name: &str,
+        options: &'a WriteOptions,
+    ) -> Result<Self, Error> {
+        // Each worksheet needs room for the title, the header and at least one data row.
+        let title_rows = u32::from(options.title.is_some());
+        if options.start_row >= MAX_ROWS - 1 - title_rows
+            || usize::from(options.start_col) >= MAX_COLS
+        {
+            return Err(Error::StartCellOutOfRange {
+                row: options.start_row,
+                col: options.start_col,
+            });
+        }
+        let sheet_name = sanitize_sheet_name(sheet_name);
+        let base_name = match &options.sheet_namer {
+            Some(namer) => namer.name(0),
+            None if options.sheet_per_batch => suffixed_sheet_name(&sheet_name, "_0"),
+            None => sheet_name.clone(),
+        };
+        let worksheet = new_worksheet(book, &base_name, options)?;
+        Ok(Self {
+            options,
+            sheet_name,
+            base_name,
+            part: 1,
+            batches: 0,
+            batches_read: 0,
+            defined_names: 0,
+            schema: None,
+            header: Vec::new(),
+            specs: Arc::new([]),
+            formats: FormatCache::default(),
+            notes: None,
+            runs: Vec::new(),
+            stats: Vec::new(),
+            worksheet,
+            sheets: 1,
+            row_offset: options.start_row,
+            transposed_records: 0,
+            columns: 0,
+            rows_written: 0,
+            rows_skipped: 0,
+            unsupported_cells: 0,
+            truncated_cells: 0,
+        })
+    }
+
+    /// Checks the sheet columns of the schema and writes the header row.
+    ///
+    /// Every batch written afterwards must have the same fields as this schema.
+    fn start(&mut self, schema: &Schema) -> Result<(), Error> {
+        self.schema = Some(Arc::new(schema.clone()));
+        let projected;
+        let schema = match &self.options.notes_column {
+            Some((notes, target)) => {
+                let index = schema
+                    .index_of(notes)
+                    .map_err(|_| column_not_found(notes, schema.fields()))?;
+                let others: Vec<usize> =
+                    (0..schema.fields().len()).filter(|&i| i != index).collect();
+                projected = schema.project(&others)?;
+                let target = leaf_fields(projected.fields())
+                    .iter()
+                    .position(|(name, _)| name == target)
+                    .ok_or_else(|| column_not_found(target, projected.fields()))?
+                    + usize::from(self.options.row_numbers);
+                self.notes = Some(NotesColumn {
+                    index,
+                    others,
+                    target,
+                });
+                &projected
+            }
+            None => schema,
+        };
+        let mut fields = leaf_fields(schema.fields());
+        let row_number_field = Field::new(ROW_NUMBER_HEADER, DataType::UInt64, false);
+        if self.options.row_numbers {
+            fields.insert(0, (ROW_NUMBER_HEADER.into(), &row_number_field));
+        }
+        let max_cols = if self.options.transpose {
+            (MAX_ROWS - self.first_row()) as usize
+        } else {
+            MAX_COLS - usize::from(self.options.start_col)
+        };
+        if fields.len() > max_cols {
+            return Err(Error::TooManyColumns {
+                found: fields.len(),
+                max: max_cols,
+            });
+        }
+        let options = self.options;
+        let data_columns = fields.len() - usize::from(options.row_numbers);
+        let overrides = &options.header_overrides;
+        if !overrides.is_empty() && overrides.len() != data_columns {
+            return Err(Error::HeaderCountMismatch {
+                expected: data_columns,
+                found: overrides.len(),
+            });
+        }
+        if let Some(name) = options
+            .formula_columns
+            .iter()
+            .chain(&options.merge_runs)
+            .find(|name| !fields.iter().any(|(leaf, _)| leaf == *name))
+        {
+            return Err(column_not_found(name, schema.fields()));
+        }
+        // A user cell writer may handle the types that are not supported here.
+        if options.strict
+            && options.cell_writer.is_none()
+            && let Some((_, field)) = fields.iter().find(|(_, f)| !is_supported(f.data_type()))
+        {
+            return Err(Error::UnsupportedType(field.data_type().clone()));
+        }
+        if !options.constant_memory && !options.transpose && options.table_style.is_none() {
+            self.runs = fields
+                .iter()
+                .enumerate()
+                .filter(|(_, (name, _))| options.merge_runs.contains(name))
+                .map(|(index, _)| MergeRun::new(index))
+                .collect();
+        }
+        self.columns = fields.len();
+        self.stats = vec![ColumnStats::default(); fields.len()];
+        let mut specs = fields
+            .iter()
+            .map(|(name, field)| {
+                let mut spec = ColumnSpec::new(field, options, &mut self.formats)?;
+                spec.formula = options.formula_columns.contains(name);
+                Ok(spec)
+            })
+            .collect::<Result<Vec<_>, arrow::error::ArrowError>>()?;
+        if self.options.row_numbers {
+            specs[0].format = self.options.header_format.clone().map(Arc::new);
+            specs[0].numeric = false;
+        }
+        self.specs = specs.into();
+        if self.options.write_header {
+            self.header = fields.into_iter().map(|(name, _)| name).collect();
+            if !overrides.is_empty() {
+                let first = usize::from(options.row_numbers);
+                self.header[first..].clone_from_slice(overrides);
+            }
+        }
+        self.write_title()?;
+        self.write_header()?;
+        self.freeze_panes()
+    }
+
+    /// Returns the row of the header, or of the first data row if there is no header: the start
+    /// row, below the [`WriteOptionsBuilder::title`] if there is one.
+    fn first_row(&self) -> u32 {
+        self.options.start_row + u32::from(self.options.title.is_some())
+    }
+
+    /// Writes the [`WriteOptionsBuilder::title`] row, merged across the sheet columns.
+    fn write_title(&mut self) -> Result<(), Error> {
+        let Some(title) = &self.options.title else {
+            return Ok(());
+        };
+        let format = self.formats.get(FormatKey::Title);
+        let (row, col) = (self.row_offset, self.options.start_col);
+        let last_col = col + self.columns.saturating_sub(1) as u16;
+        if self.options.transpose || last_col == col {
+            self.worksheet
+                .write_string_with_format(row, col, title, &format)?;
+        } else {
+            self.worksheet
+                .merge_range(row, col, row, last_col, title, &format)?;
+        }
+        self.row_offset += 1;
+        Ok(())
+    }
+
+    fn write_header(&mut self) -> Result<(), Error> {
+        if self.header.is_empty() {
+            return Ok(());
+        }
+        let format = self.options.header_format.as_ref();
+        if self.options.transpose {
+            for (row, name) in self.header.iter().enumerate() {
+                write_string(
+                    &mut self.worksheet,
+                    self.row_offset + row as u32,
+                    self.options.start_col,
+                    name,
+                    format,
+                )?;
+            }
+            return Ok(());
+        }
+        for (col, name) in self.header.iter().enumerate() {
+            write_string(
+                &mut self.worksheet,
+                self.row_offset,
+                self.options.start_col + col as u16,
+                name,
+                format,
+            )?;
+        }
+        self.row_offset += 1;
+        Ok(())
+    }
+
+    /// Freezes the header and the leading [`WriteOptionsBuilder::freeze_columns`] columns.
+    ///
+    /// When transposed, the header is a column and the leading fields are rows instead.
+    fn freeze_panes(&mut self) -> Result<(), Error> {
+        let header = !self.header.is_empty() && self.options.freeze_header;
+        let fields = u32::from(self.options.freeze_columns) + u32::from(self.options.row_numbers);
+        let (rows, cols) = if self.options.transpose {
+            let rows = if fields > 0 {
+                self.first_row() + fields
+            } else {
+                0
+            };
+            let cols = if header {
+                self.options.start_col + 1
+            } else {
+                0
+            };
+            (rows, cols)
+        } else {
+            let rows = if header { self.row_offset } else { 0 };
+            let cols = match fields {
+                0 => 0,
+                columns => self.options.start_col.saturating_add(columns as u16),
+            };
+            (rows, cols)
+        };
+        if rows > 0 || cols > 0 {
+            self.worksheet.set_freeze_panes(rows, cols)?;
+        }
+        Ok(())
+    }
+
+    /// Adds the current worksheet to the workbook and continues on a continuation sheet.
+    fn next_sheet(&mut self, book: &mut Workbook) -> Result<(), Error> {
+        self.part += 1;
+        let sheet_name = match &self.options.sheet_namer {
+            Some(namer) => namer.name(self.sheets as usize),
+            None => suffixed_sheet_name(&self.base_name, &format!(" ({})", self.part)),
+        };
+        self.replace_worksheet(book, &sheet_name)
+    }
+
+    /// Adds the current worksheet to the workbook and continues on a new one with the given name.
+    fn replace_worksheet(&mut self, book: &mut Workbook, sheet_name: &str) -> Result<(), Error> {
+        log::debug!("continuing on worksheet {:?}", sheet_name);
+        self.sheets += 1;
+        let worksheet = new_worksheet(book, sheet_name, self.options)?;
+        self.finish_worksheet()?;
+        self.define_name(book)?;
+        let full = std::mem::replace(&mut self.worksheet, worksheet);
+        book.push_worksheet(full);
+        self.row_offset = self.options.start_row;
+        self.transposed_records = 0;
+        self.write_title()?;
+        self.write_header()?;
+        self.freeze_panes()
+    }
+
+    /// Returns whether [`WriteOptionsBuilder::max_rows`] data rows have been written, or the
+    /// batches of [`WriteOptionsBuilder::batch_range`] have been read.
+    fn is_full(&self) -> bool {
+        self.options
+            .max_rows
+            .is_some_and(|max_rows| self.rows_written >= max_rows)
+            || self
+                .options
+                .batch_range
+                .as_ref()
+                .is_some_and(|range| self.batches_read > *range.end())
+    }
+
+    fn write_batch(&mut self, book: &mut Workbook, batch: &RecordBatch) -> Result<(), Error> {
+        if let Some(schema) = &self.schema
+            && schema.fields() != batch.schema().fields()
+        {
+            return Err(Error::SchemaMismatch {
+                expected: schema.clone(),
+                found: batch.schema(),
+            });
+        }
+
+        if self.is_full() {
+            return Ok(());
+        }
+        let index = self.batches_read;
+        self.batches_read += 1;
+        if let Some(range) = &self.options.batch_range
+            && !range.contains(&index)
+        {
+            return Ok(());
+        }
+        let skipped;
+        let batch = if self.rows_skipped < self.options.skip_rows {
+            let skip = (self.options.skip_rows - self.rows_skipped).min(batch.num_rows() as u64);
+            self.rows_skipped += skip;
+            if skip as usize == batch.num_rows() {
+                return Ok(());
+            }
+            skipped = batch.slice(skip as usize, batch.num_rows() - skip as usize);
+            &skipped
+        } else {
+            batch
+        };
+        let limited;
+        let batch = match self.options.max_rows {
+            Some(max_rows) if self.rows_written + batch.num_rows() as u64 > max_rows => {
+                limited = batch.slice(0, (max_rows - self.rows_written) as usize);
+                &limited
+            }
+            _ => batch,
+        };
+
+        if self.options.sheet_per_batch && self.batches > 0 {
+            self.base_name = match &self.options.sheet_namer {
+                Some(namer) => namer.name(self.sheets as usize),
+                None => suffixed_sheet_name(&self.sheet_name, &format!("_{}", self.batches)),
+            };
+            self.part = 1;
+            let sheet_name = self.base_name.clone();
+            self.replace_worksheet(book, &sheet_name)?;
+        }
+        self.batches += 1;
+        log::debug!(
+            "writing batch {} of {} rows to worksheet {:?}",
+            index,
+            batch.num_rows(),
+            self.worksheet.name()
+        );
+
+        let notes_array;
+        let projected;
+        let (batch, notes) = match &self.notes {
+            Some(notes) => {
+                notes_array = cast(batch.column(notes.index), &DataType::Utf8)?;
+                projected = batch.project(&notes.others)?;
+                (
+                    &projected,
+                    Some((notes_array.as_string::<i32>(), notes.target)),
+                )
+            }
+            None => (batch, None),
+        };
+
+        if self.options.transpose {
+            return self.write_batch_transposed(batch, notes);
+        }
+
+        let columns = self.sheet_columns(batch)?;
+        for (stats, column) in self.stats.iter_mut().zip(&columns) {
+            stats.update(column)?;
+        }
+        let specs = Arc::clone(&self.specs);
+        let writers = cell_writers(&columns, &specs, self.options);
+
+        let mut row = 0;
+        while row < batch.num_rows() {
+            if self.row_offset >= MAX_ROWS {
+                self.next_sheet(book)?;
+            }
+            // The rows that fit in the current worksheet, so `row_offset + len <= MAX_ROWS`.
+            let room = MAX_ROWS - self.row_offset;
+            let len = u32::try_from(batch.num_rows() - row).map_or(room, |rest| rest.min(room));
+            let rows = row..row + len as usize;
+
+            if self.options.constant_memory {
+                // A row is flushed once a later row is written to, so each row is completed
+                // before the next one and at most one row of the batch is buffered.
+                for (row_offset, row) in (self.row_offset..).zip(rows.clone()) {
+                    for (col, (write_cell, column)) in writers.iter().zip(&columns).enumerate() {
+                        let col = self.options.start_col + col as u16;
+                        self.write_column(write_cell, column, col, row..row + 1, row_offset)?;
+                    }
+                }
+            } else {
+                for (col, (write_cell, column)) in writers.iter().zip(&columns).enumerate() {
+                    let col = self.options.start_col + col as u16;
+                    self.write_column(write_cell, column, col, rows.clone(), self.row_offset)?;
+                }
+                for run in &mut self.runs {
+                    run.track(
+                        &mut self.worksheet,
+                        &columns[run.index],
+                        rows.clone(),
+                        self.row_offset,
+                        &specs[run.index],
+                        self.options,
+                    )?;
+                }
+            }
+
+            if let Some((notes, target)) = notes {
+                let col = self.options.start_col + target as u16;
+                for (row_offset, row) in (self.row_offset..).zip(rows) {
+                    if let Some(note) = note_text(notes, row) {
+                        self.worksheet
+                            .insert_note(row_offset, col, &Note::new(note))?;
+                    }
+                }
+            }
+            self.row_offset += len;
+            self.rows_written += u64::from(len);
+            row += len as usize;
+        }
+        self.truncated_cells += specs.iter().map(ColumnSpec::take_truncated).sum::<u64>();
+        Ok(())
+    }
+
+    /// Writes the cells of the given rows of a column downward from `row_offset`.
+    fn write_column(
+        &mut self,
+        write_cell: &CellWriter,
+        column: &LeafColumn,
+        col: u16,
+        rows: Range<usize>,
+        row_offset: u32,
+    ) -> Result<(), Error> {
+        for (row_offset, row) in (row_offset..).zip(rows) {
+            if !write_cell(&mut self.worksheet, row_offset, col, row)? {
+                if self.options.strict {
+                    return Err(Error::UnsupportedType(column.array.data_type().clone()));
+                }
+                self.unsupported_cells += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the leaf columns of the batch, after the row numbers if they are enabled.
+    fn sheet_columns(&self, batch: &RecordBatch) -> Result<Vec<LeafColumn>, Error> {
+        let mut columns = leaf_columns(batch)?;
+        if self.options.row_numbers {
+            let first = self.rows_written + 1;
+            let numbers = UInt64Array::from_iter_values(first..first + batch.num_rows() as u64);
+            columns.insert(0, LeafColumn::new(Arc::new(numbers)));
+        }
+        Ok(columns)
+    }
+
+    /// Writes each record of the batch as a column to the right of the previous ones.
+    fn write_batch_transposed(
+        &mut self,
+        batch: &RecordBatch,
+        notes: Option<(&StringArray, usize)>,
+    ) -> Result<(), Error> {
+        let columns = self.sheet_columns(batch)?;
+        for (stats, column) in self.stats.iter_mut().zip(&columns) {
+            stats.update(column)?;
+        }
+        let specs = Arc::clone(&self.specs);
+        let writers = cell_writers(&columns, &specs, self.options);
+        let first_col = usize::from(self.options.start_col) + usize::from(!self.header.is_empty());
+
+        for row in 0..batch.num_rows() {
+            let col = first_col + self.transposed_records;
+            if col >= MAX_COLS {
+                return Err(Error::TooManyColumns {
+                    found: col + 1,
+                    max: MAX_COLS,
+                });
+            }
+            for (index, (write_cell, column)) in writers.iter().zip(&columns).enumerate() {
+                let row_offset = self.first_row() + index as u32;
+                let supported = write_cell(&mut self.worksheet, row_offset, col as u16, row)?;
+                if !supported {
+                    if self.options.strict {
+                        return Err(Error::UnsupportedType(column.array.data_type().clone()));
+                    }
+                    self.unsupported_cells += 1;
+                }
+            }
+            if let Some((notes, target)) = notes
+                && let Some(note) = note_text(notes, row)
+            {
+                let row = self.first_row() + target as u32;
+                self.worksheet
+                    .insert_note(row, col as u16, &Note::new(note))?;
+            }
+            self.transposed_records += 1;
+            self.rows_written += 1;
+        }
+        self.truncated_cells += specs.iter().map(ColumnSpec::take_truncated).sum::<u64>();
+        Ok(())
+    }
+
+    /// Returns the first row, first column, last row and last column of the header and data
+    /// cells of the current worksheet, or `None` if it has no data rows.
+    ///
+    /// When [`WriteOptionsBuilder::transpose`] is enabled, the fields go down the rows and the
+    /// records across the columns.
+    fn data_range(&self) -> Option<(u32, u16, u32, u16)> {
+        let first_row = self.first_row();
+        if self.options.transpose {
+            if self.columns == 0 || self.transposed_records == 0 {
+                return None;
+            }
+            let first_col = self.options.start_col;
+            let last_col = usize::from(first_col)
+                + usize::from(!self.header.is_empty())
+                + self.transposed_records
+                - 1;
+            let last_row = first_row + (self.columns - 1) as u32;
+            return Some((first_row, first_col, last_row, last_col as u16));
+        }
+        let header_rows = u32::from(!self.header.is_empty());
+        if self.columns == 0 || self.row_offset <= first_row + header_rows {
+            return None;
+        }
+        let first_col = self.options.start_col;
+        let last_col = first_col + (self.columns - 1) as u16;
+        Some((first_row, first_col, self.row_offset - 1, last_col))
+    }
+
+    /// Applies the options that need all rows of the current worksheet to be written.
+    fn finish_worksheet(&mut self) -> Result<(), Error> {
+        for run in &mut self.runs {
+            run.merge(&mut self.worksheet, &self.specs[run.index], self.options)?;
+            run.last = None;
+        }
+        if !self.options.transpose
+            && let Some((first_row, first_col, last_row, last_col)) = self.data_range()
+        {
+            let first_data_row = first_row + u32::from(!self.header.is_empty());
+            if self.options.color_scale_numeric {
+                let color_scale = ConditionalFormat3ColorScale::new();
+                for (index, spec) in self.specs.iter().enumerate() {
+                    if spec.numeric {
+                        let col = first_col + index as u16;
+                        self.worksheet.add_conditional_format(
+                            first_data_row,
+                            col,
+                            last_row,
+                            col,
+                            &color_scale,
+                        )?;
+                    }
+                }
+            }
+            if self.options.totals_row && last_row + 1 < MAX_ROWS {
+                for (index, spec) in self.specs.iter().enumerate() {
+                    if !spec.numeric {
+                        continue;
+                    }
+                    let col = first_col + index as u16;
+                    let range = cell_range(first_data_row, col, last_row, col);
+                    let formula = Formula::new(format!("=SUM({range})"));
+                    match spec.format.as_deref() {
+                        Some(format) => self.worksheet.write_formula_with_format(
+                            last_row + 1,
+                            col,
+                            formula,
+                            format,
+                        )?,
+                        None => self.worksheet.write_formula(last_row + 1, col, formula)?,
+                    };
+                }
+            }
+            if let Some(TableStyleOption(style)) = self.options.table_style {
+                let columns: Vec<TableColumn> = self
+                    .header
+                    .iter()
+                    .map(|name| {
+                        let column = TableColumn::new().set_header(name);
+                        match &self.options.header_format {
+                            Some(format) => column.set_header_format(format),
+                            None => column,
+                        }
+                    })
+                    .collect();
+                let table = Table::new()
+                    .set_style(style)
+                    .set_header_row(!self.header.is_empty())
+                    .set_columns(&columns);
+                self.worksheet
+                    .add_table(first_row, first_col, last_row, last_col, &table)?;
+            } else if self.options.autofilter {
+                self.worksheet
+                    .autofilter(first_row, first_col, last_row, last_col)?;
+            }
+        }
+        if self.options.autofit && !self.options.constant_memory {
+            self.worksheet.autofit();
+        }
+        if !self.options.transpose {
+            for (index, spec) in self.specs.iter().enumerate() {
+                if let Some(width) = spec.width {
+                    let col = self.options.start_col + index as u16;
+                    self.worksheet.set_column_width(col, width)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Defines the [`WriteOptionsBuilder::define_name`] name over the header and data cells of
+    /// the current worksheet.
+    fn define_name(&mut self, book: &mut Workbook) -> Result<(), Error> {
+        let Some(name) = &self.options.define_name else {
+            return Ok(());
+        };
+        let Some((first_row, first_col, last_row, last_col)) = self.data_range() else {
+            return Ok(());
+        };
+        self.defined_names += 1;
+        let name = match self.defined_names {
+            1 => name.clone(),
+            part => format!("{name}_{part}"),
+        };
+        let range = worksheet_range_absolute(
+            self.worksheet.name().as_str(),
+            first_row,
+            first_col,
+            last_row,
+            last_col,
+        );
+        book.define_name(name, &format!("={range}"))?;
+        Ok(())
+    }
+
+    /// Adds the current worksheet to the workbook.
+    fn finish(mut self, book: &mut Workbook) -> Result<WriteSummary, Error> {
+        self.finish_worksheet()?;
+        self.define_name(book)?;
+        book.push_worksheet(self.worksheet);
+        if self.options.emit_schema_sheet
+            && let Some(schema) = &self.schema
+        {
+            push_schema_sheet(book, schema, self.options)?;
+            self.sheets += 1;
+        }
+        log::debug!(
+            "wrote {} rows of {} batches to {} worksheets",
+            self.rows_written,
+            self.batches,
+            self.sheets
+        );
+        Ok(WriteSummary {
+            rows_written: self.rows_written,
+            sheets_written: self.sheets,
+            columns: self.columns,
+            unsupported_cells: self.unsupported_cells,
+            truncated_cells: self.truncated_cells,
+            column_stats: self.stats,
+        })
+    }
+}
+
+/// The name of the worksheet added by [`WriteOptionsBuilder::emit_schema_sheet`].
+pub const SCHEMA_SHEET_NAME: &str = "Schema";
+
+/// Adds a worksheet listing the fields of the schema, one per row.
+fn push_schema_sheet(
+    book: &mut Workbook,
+    schema: &Schema,
+    options: &WriteOptions,
+) -> Result<(), Error> {
+    // Excel compares sheet names case-insensitively.
+    let used_names: Vec<String> = book
+        .worksheets()
+        .iter()
+        .map(|worksheet| worksheet.name().to_lowercase())
+        .collect();
+    let mut sheet_name = SCHEMA_SHEET_NAME.to_string();
+    let mut part = 1;
+    while used_names.contains(&sheet_name.to_lowercase()) {
+        part += 1;
+        sheet_name = format!("{SCHEMA_SHEET_NAME} ({part})");
+    }
+
+    let mut worksheet = Worksheet::new();
+    worksheet.set_name(&sheet_name)?;
+    let format = options.header_format.as_ref();
+    for (col, name) in ["Name", "Type", "Nullable", "Metadata"]
+        .into_iter()
+        .enumerate()
+    {
+        write_string(&mut worksheet, 0, col as u16, name, format)?;
+    }
+    worksheet.set_freeze_panes(1, 0)?;
+
+    for (index, field) in schema.fields().iter().enumerate() {
+        let row = index as u32 + 1;
+        worksheet.write_string(row, 0, field.name())?;
+        worksheet.write_string(row, 1, field.data_type().to_string())?;
+        worksheet.write_boolean(row, 2, field.is_nullable())?;
+        if !field.metadata().is_empty() {
+            let mut entries: Vec<_> = field.metadata().iter().collect();
+            entries.sort();
+            let mut json = String::from("{");
+            for (position, (key, value)) in entries.into_iter().enumerate() {
+                if position > 0 {
+                    json.push(',');
+                }
+                push_json_string(&mut json, key);
+                json.push(':');
+                push_json_string(&mut json, value);
+            }
+            json.push('}');
+            worksheet.write_string(row, 3, json)?;
+        }
+    }
+    worksheet.autofit();
+    book.push_worksheet(worksheet);
+    Ok(())
+}
+
+/// Creates a worksheet that is added to the workbook once it is finished.
+fn new_worksheet(
+    book: &mut Workbook,
+    sheet_name: &str,
+    options: &WriteOptions,
+) -> Result<Worksheet, XlsxError> {
+    let mut worksheet = if options.constant_memory && !options.transpose {
+        book.new_worksheet_with_constant_memory()
+    } else {
+        Worksheet::new()
+    };
+    worksheet.set_name(sheet_name)?;
+    match &options.protect {
+        Some(Some(password)) => {
+            worksheet.protect_with_password(password);
+        }
+        Some(None) => {
+            worksheet.protect();
+        }
+        None => {}
+    }
+    Ok(worksheet)
+}
+
+/// Returns the header names and fields of the sheet columns, flattening struct fields into
+/// `parent.child`.
+fn leaf_fields(fields: &Fields) -> Vec<(String, &Field)> {
+    let mut leaves = Vec::with_capacity(fields.len());
+    for field in fields {
+        push_leaf_fields(&mut leaves, field.name().clone(), field);
+    }
+    leaves
+}
+
+fn push_leaf_fields<'a>(leaves: &mut Vec<(String, &'a Field)>, name: String, field: &'a Field) {
+    match field.data_type() {
+        DataType::Struct(children) => {
+            for child in children {
+                let child_name = format!("{}.{}", name, child.name());
+                push_leaf_fields(leaves, child_name, child);
+            }
+        }
+        _ => leaves.push((name, field)),
+    }
+}
+
+/// A column of a sheet, flattened out of the struct columns it is nested in.
+struct LeafColumn {
+    array: ArrayRef,
+    /// The validity of the enclosing struct, kept apart for run-end encoded and union arrays,
+    /// which cannot have a null bitmask of their own.
+    parent_nulls: Option<NullBuffer>,
+}
+
+impl LeafColumn {
+    fn new(array: ArrayRef) -> Self {
+        Self {
+            array,
+            parent_nulls: None,
+        }
+    }
+
+    /// Returns whether the value at the row is null, in the array or in an enclosing struct.
+    fn is_null(&self, row: usize) -> bool {
+        self.parent_nulls
+            .as_ref()
+            .is_some_and(|nulls| nulls.is_null(row))
+            || self.array.is_null(row)
+    }
+
+    /// Returns the number of null values, counting those of the enclosing structs.
+    fn logical_null_count(&self) -> usize {
+        match &self.parent_nulls {
+            Some(parent_nulls) => {
+                NullBuffer::union(Some(parent_nulls), self.array.logical_nulls().as_ref())
+                    .map_or(0, |nulls| nulls.null_count())
+            }
+            None => self.array.logical_null_count(),
+        }
+    }
+}
+
+/// Returns the arrays of the sheet columns, flattening struct columns in the same order as
+/// [`leaf_fields`].
+///
+/// A null struct value is pushed down to its children so that it yields empty cells.
+fn leaf_columns(batch: &RecordBatch) -> Result<Vec<LeafColumn>, arrow::error::ArrowError> {
+    let mut columns = Vec::with_capacity(batch.num_columns());
+    for column in batch.columns() {
+        push_leaf_columns(&mut columns, LeafColumn::new(column.clone()))?;
+    }
+    Ok(columns)
+}
+
+fn push_leaf_columns(
+    columns: &mut Vec<LeafColumn>,
+    column: LeafColumn,
+) -> Result<(), arrow::error::ArrowError> {
+    let DataType::Struct(_) = column.array.data_type() else {
+        columns.push(column);
+        return Ok(());
+    };
+
+    let array = column.array.as_struct();
+    for child in array.columns() {
+        let child = match (array.nulls(), child.data_type()) {
+            (Some(nulls), DataType::RunEndEncoded(_, _) | DataType::Union(_, _)) => LeafColumn {
+                array: child.clone(),
+                parent_nulls: Some(nulls.clone()),
+            },
+            (Some(_), data_type) if data_type != &DataType::Null => {
+                let nulls = NullBuffer::union(array.nulls(), child.nulls());
+                LeafColumn::new(make_array(
+                    child.to_data().into_builder().nulls(nulls).build()?,
+                ))
+            }
+            _ => LeafColumn::new(child.clone()),
+        };
+        push_leaf_columns(columns, child)?;
+    }
+    Ok(())
+}
+
+/// The largest integer magnitude an f64 can represent exactly (2^53).
+///
+/// 64-bit integers and decimals beyond this magnitude are written as text to keep every digit.
+pub const MAX_EXACT_F64_INT: u64 = 1 << 53;
+
+/// The maximum number of characters Excel allows in a single cell.
+pub const MAX_CELL_CHARS: usize = 32_767;
+
+/// Shortens the text to [`WriteOptionsBuilder::max_cell_chars`] characters, ending it with `…`,
+/// and counts it in the column settings when it is longer.
+fn truncate_cell_text<'t>(
+    text: &'t str,
+    spec: &ColumnSpec,
+    options: &WriteOptions,
+) -> Cow<'t, str> {
+    let max_chars = options.max_cell_chars;
+    if text.len() <= max_chars {
+        return Cow::Borrowed(text);
+    }
+    match text.char_indices().nth(max_chars) {
+        Some(_) => {
+            spec.truncated.fetch_add(1, Ordering::Relaxed);
+            let kept: String = text.chars().take(max_chars - 1).collect();
+            Cow::Owned(kept + "…")
+        }
+        None => Cow::Borrowed(text),
+    }
+}
+
+/// How binary values are written as text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BinaryEncoding {
+    /// Lowercase hexadecimal, e.g. `dead01`.
+    #[default]
+    Hex,
+    /// Standard base64 with padding, e.g. `3q0B`.
+    Base64,
+}
+
+impl BinaryEncoding {
+    /// Parses the name of an encoding, `hex` or `base64`, ignoring case.
+    fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "hex" => Some(BinaryEncoding::Hex),
+            "base64" => Some(BinaryEncoding::Base64),
+            _ => None,
+        }
+    }
+
+    fn encode(self, bytes: &[u8]) -> String {
+        match self {
+            BinaryEncoding::Hex => hex_encode(bytes),
+            BinaryEncoding::Base64 => BASE64_STANDARD.encode(bytes),
+        }
+    }
+}
+
+/// Encodes the bytes as a lowercase hex string.
+fn hex_encode(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(hex, "{byte:02x}");
+    }
+    hex
+}
+
+/// Formats a number of months as years and months, e.g. `1y 2m`.
+fn format_year_month(months: i32) -> String {
+    format!("{}y {}m", months / 12, months % 12)
+}
+
+/// Formats a signed number of nanoseconds as `hh:mm:ss`, with a fraction only when non-zero.
+fn format_clock(nanos: i64) -> String {
+    let sign = if nanos < 0 { "-" } else { "" };
+    let nanos = nanos.unsigned_abs();
+    let secs = nanos / 1_000_000_000;
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+    let fraction = nanos % 1_000_000_000;
+    if fraction == 0 {
+        return format!("{sign}{hours:02}:{minutes:02}:{seconds:02}");
+    }
+
+    let mut fraction = format!("{fraction:09}");
+    while fraction.ends_with("000") {
+        fraction.truncate(fraction.len() - 3);
+    }
+    format!("{sign}{hours:02}:{minutes:02}:{seconds:02}.{fraction}")
+}
+
+/// Appends the string as a quoted JSON string.
+fn push_json_string(out: &mut String, value: &str) {
+    use std::fmt::Write;
+
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Appends the elements of a list value as a JSON array.
+fn push_json_array(out: &mut String, values: &dyn Array) {
+    out.push('[');
+    for index in 0..values.len() {
+        if index > 0 {
+            out.push(',');
+        }
+        push_json_value(out, values, index);
+    }
+    out.push(']');
+}
+
+/// Appends the entries of a map value as a JSON object.
+///
+/// Keys that are not strings are written as their display string.
+fn push_json_object(out: &mut String, entries: &StructArray) {
+    let keys = entries.column(0);
+    let values = entries.column(1);
+    out.push('{');
+    for index in 0..entries.len() {
+        if index > 0 {
+            out.push(',');
+        }
+        match keys.data_type() {
+            DataType::Utf8 => push_json_string(out, keys.as_string::<i32>().value(index)),
+            DataType::LargeUtf8 => push_json_string(out, keys.as_string::<i64>().value(index)),
+            DataType::Utf8View => push_json_string(out, keys.as_string_view().value(index)),
+            _ => {
+                let options = FormatOptions::default();
+                match ArrayFormatter::try_new(keys.as_ref(), &options) {
+                    Ok(formatter) => push_json_string(out, &formatter.value(index).to_string()),
+                    Err(_) => push_json_string(out, ""),
+                }
+            }
+        }
+        out.push(':');
+        push_json_value(out, values.as_ref(), index);
+    }
+    out.push('}');
+}
+
+/// Appends the value at `index` as JSON.
+///
+/// Nulls and non-finite floats become `null`; types without a natural JSON form are written
+/// as their display string.
+fn push_json_value(out: &mut String, array: &dyn Array, index: usize) {
+    use std::fmt::Write;
+
+    if array.is_null(index) || array.data_type() == &DataType::Null {
+        out.push_str("null");
+        return;
+    }
+
+    macro_rules! push_display {
+        ($type:ty) => {{
+            let _ = write!(out, "{}", array.as_primitive::<$type>().value(index));
+        }};
+    }
+
+    macro_rules! push_float {
+        ($value:expr) => {{
+            let value = $value;
+            if value.is_finite() {
+                let _ = write!(out, "{:?}", value);
+            } else {
+                out.push_str("null");
+            }
+        }};
+    }
+
+    match array.data_type() {
+        DataType::Boolean => {
+            let value = array.as_boolean().value(index);
+            out.push_str(if value { "true" } else { "false" });
+        }
+        DataType::Int8 => push_display!(Int8Type),
+        DataType::Int16 => push_display!(Int16Type),
+        DataType::Int32 => push_display!(Int32Type),
+        DataType::Int64 => push_display!(Int64Type),
+        DataType::UInt8 => push_display!(UInt8Type),
+        DataType::UInt16 => push_display!(UInt16Type),
+        DataType::UInt32 => push_display!(UInt32Type),
+        DataType::UInt64 => push_display!(UInt64Type),
+        DataType::Float16 => push_float!(array.as_primitive::<Float16Type>().value(index)),
+        DataType::Float32 => push_float!(array.as_primitive::<Float32Type>().value(index)),
+        DataType::Float64 => push_float!(array.as_primitive::<Float64Type>().value(index)),
+        DataType::Utf8 => push_json_string(out, array.as_string::<i32>().value(index)),
+        DataType::LargeUtf8 => push_json_string(out, array.as_string::<i64>().value(index)),
+        DataType::Utf8View => push_json_string(out, array.as_string_view().value(index)),
+        DataType::List(_) => push_json_array(out, &array.as_list::<i32>().value(index)),
+        DataType::LargeList(_) => push_json_array(out, &array.as_list::<i64>().value(index)),
+        DataType::FixedSizeList(_, _) => {
+            push_json_array(out, &array.as_fixed_size_list().value(index))
+        }
+        DataType::Map(_, _) => push_json_object(out, &array.as_map().value(index)),
+        _ => {
+            let options = FormatOptions::default();
+            match ArrayFormatter::try_new(array, &options) {
+                Ok(formatter) => push_json_string(out, &formatter.value(index).to_string()),
+                Err(_) => out.push_str("null"),
+            }
+        }
+    }
+}
+
+/// Returns the Excel number format for a decimal with the given scale (e.g. `0.00` for 2), with
+/// a thousands separator if `grouping` is set (e.g. `#,##0.00`).
+fn decimal_format(scale: i8, grouping: bool) -> String {
+    let integer = if grouping { "#,##0" } else { "0" };
+    if scale <= 0 {
+        integer.into()
+    } else {
+        format!("{integer}.{}", "0".repeat(scale as usize))
+    }
+}
+
+/// Returns the per-column number format to apply to data cells, if any.
+fn column_format(
+    data_type: &DataType,
+    options: &WriteOptions,
+    formats: &mut FormatCache,
+) -> Option<Arc<Format>> {
+    let num_format = match data_type {
+        DataType::Date32 | DataType::Date64 => options.date_format.clone(),
+        DataType::Decimal128(_, scale) => decimal_format(*scale, options.decimal_grouping),
+        DataType::Timestamp(TimeUnit::Second, _) => "yyyy-mm-dd hh:mm:ss".into(),
+        DataType::Timestamp(_, _) => "yyyy-mm-dd hh:mm:ss.000".into(),
+        DataType::Time32(TimeUnit::Second) => "hh:mm:ss".into(),
+        DataType::Time32(_) | DataType::Time64(_) => "hh:mm:ss.000".into(),
+        _ => return None,
+    };
+    Some(formats.get(FormatKey::Number(num_format)))
+}
+
+/// Describes a format created by the writer, rather than one given in the options.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum FormatKey {
+    /// A number format, such as `yyyy-mm-dd` or `#,##0.00`.
+    Number(String),
+    /// The format of the [`WriteOptionsBuilder::title`] row.
+    Title,
+}
+
+impl FormatKey {
+    fn format(&self) -> Format {
+        match self {
+            Self::Number(num_format) => Format::new().set_num_format(num_format),
+            Self::Title => Format::new()
+                .set_bold()
+                .set_font_size(14)
+                .set_align(FormatAlign::Center),
+        }
+    }
+}
+
+/// The formats of a [`SheetWriter`], each created once and shared by every column and cell that
+/// uses it, however many columns have the same type or metadata.
+#[derive(Default)]
+struct FormatCache {
+    formats: HashMap<FormatKey, Arc<Format>>,
+}
+
+impl FormatCache {
+    fn get(&mut self, key: FormatKey) -> Arc<Format> {
+        let format = self
+            .formats
+            .entry(key)
+            .or_insert_with_key(|key| Arc::new(key.format()));
+        Arc::clone(format)
+    }
+}
+
+fn write_string(
+    worksheet: &mut Worksheet,
+    row: u32,
+    col: u16,
+    text: &str,
+    format: Option<&Format>,
+) -> Result<(), XlsxError> {
+    match format {
+        Some(format) => worksheet.write_string_with_format(row, col, text, format)?,
+        None => worksheet.write_string(row, col, text)?,
+    };
+    Ok(())
+}
+
+/// Writes a text made from a value of a column, shortened by `truncate_cell_text`.
+fn write_cell_text(
+    worksheet: &mut Worksheet,
+    row: u32,
+    col: u16,
+    text: &str,
+    spec: &ColumnSpec,
+    options: &WriteOptions,
+) -> Result<(), XlsxError> {
+    let text = truncate_cell_text(text, spec, options);
+    write_string(worksheet, row, col, &text, spec.format.as_deref())
+}
+
+/// Writes a float as a number, or as the configured text if it is NaN or infinite.
+fn write_float(
+    worksheet: &mut Worksheet,
+    row: u32,
+    col: u16,
+    number: f64,
+    spec: &ColumnSpec,
+    options: &WriteOptions,
+) -> Result<(), XlsxError> {
+    let text = if number.is_nan() {
+        &options.nan_text
+    } else if number == f64::INFINITY {
+        &options.infinity_text
+    } else if number == f64::NEG_INFINITY {
+        &options.neg_infinity_text
+    } else {
+        return write_number(worksheet, row, col, number, spec.format.as_deref());
+    };
+    write_cell_text(worksheet, row, col, text, spec, options)
+}
+
+/// The maximum number of characters in an Excel hyperlink.
+const MAX_URL_CHARS: usize = 2_080;
+
+/// Returns whether the text is an `http` or `https` URL that fits in a hyperlink.
+fn is_url(text: &str) -> bool {
+    let rest = text
+        .strip_prefix("https://")
+        .or_else(|| text.strip_prefix("http://"));
+    match rest {
+        Some(rest) => {
+            !rest.is_empty()
+                && !text.chars().any(char::is_whitespace)
+                && text.chars().count() <= MAX_URL_CHARS
+        }
+        None => false,
+    }
+}
+
+/// Writes the value of a string column, as a formula if it starts with `=` in a
+/// [`WriteOptionsBuilder::formula_columns`] column, or as a hyperlink if it is a URL and
+/// [`WriteOptionsBuilder::detect_hyperlinks`] is enabled.
+fn write_text(
+    worksheet: &mut Worksheet,
+    row: u32,
+    col: u16,
+    text: &str,
+    spec: &ColumnSpec,
+    options: &WriteOptions,
+) -> Result<(), XlsxError> {
+    let text = truncate_cell_text(text, spec, options);
+    let text = text.as_ref();
+    let format = spec.format.as_deref();
+    if spec.formula && text.starts_with('=') {
+        let formula = Formula::new(text);
+        match format {
+            Some(format) => worksheet.write_formula_with_format(row, col, formula, format)?,
+            None => worksheet.write_formula(row, col, formula)?,
+        };
+        return Ok(());
+    }
+    if !(options.detect_hyperlinks && is_url(text)) {
+        return write_string(worksheet, row, col, text, format);
+    }
+    match format {
+        Some(format) => worksheet.write_url_with_format(row, col, text, format)?,
+        None => worksheet.write_url(row, col, text)?,
+    };
+    Ok(())
+}
+
+fn write_number(
+    worksheet: &mut Worksheet,
+    row: u32,
+    col: u16,
+    number: f64,
+    format: Option<&Format>,
+) -> Result<(), XlsxError> {
+    match format {
+        Some(format) => worksheet.write_number_with_format(row, col, number, format)?,
+        None => worksheet.write_number(row, col, number)?,
+    };
+    Ok(())
+}
+
+fn write_datetime(
+    worksheet: &mut Worksheet,
+    row: u32,
+    col: u16,
+    datetime: impl IntoExcelDateTime,
+    format: Option<&Format>,
+) -> Result<(), XlsxError> {
+    match format {
+        Some(format) => worksheet.write_datetime_with_format(row, col, datetime, format)?,
+        None => worksheet.write_datetime(row, col, datetime)?,
+    };
+    Ok(())
+}
+
+/// The first year of the dates Excel can show in the 1900 date system, see [`WriteOptions`].
+const EXCEL_MIN_YEAR: i32 = 1900;
+
+/// Writes a date, or its ISO 8601 text if it is before [`EXCEL_MIN_YEAR`], as Excel shows a
+/// negative date serial number as `####`.
+fn write_date<D>(
+    worksheet: &mut Worksheet,
+    row: u32,
+    col: u16,
+    date: D,
+    spec: &ColumnSpec,
+    options: &WriteOptions,
+) -> Result<(), XlsxError>
+where
+    D: IntoExcelDateTime + Datelike + std::fmt::Display,
+{
+    if date.year() < EXCEL_MIN_YEAR {
+        return write_cell_text(worksheet, row, col, &date.to_string(), spec, options);
+    }
+    write_datetime(worksheet, row, col, date, spec.format.as_deref())
+}
+
+/// The field metadata key holding an Excel number format for the column, e.g. `#,##0.00`.
+pub const EXCEL_FORMAT_KEY: &str = "excel_format";
+
+/// The field metadata key holding the width of the column in characters, e.g. `25.0`.
+pub const EXCEL_WIDTH_KEY: &str = "excel_width";
+
+/// The field metadata key holding the [`BinaryEncoding`] of a binary column, `hex` or `base64`.
+pub const BINARY_ENCODING_KEY: &str = "binary_encoding";
+
+/// The field metadata key holding whether an integer column is written as text, `true` or
+/// `false`.
+pub const NUMBER_AS_TEXT_KEY: &str = "number_as_text";
+
+/// Per-column settings resolved once per sheet.
+struct ColumnSpec {
+    format: Option<Arc<Format>>,
+    tz: Option<Tz>,
+    /// Whether the cells are written as numbers, which can be summed.
+    numeric: bool,
+    /// The settings of the children of a union column, by type id.
+    union_children: Vec<(i8, ColumnSpec)>,
+    width: Option<f64>,
+    binary_encoding: BinaryEncoding,
+    /// Whether integers are written as text.
+    number_as_text: bool,
+    /// Whether strings starting with `=` are written as formulas.
+    formula: bool,
+    /// The number of cells shortened by `truncate_cell_text` since it was last taken.
+    truncated: AtomicU64,
+}
+
+impl ColumnSpec {
+    /// Returns and resets the number of cells shortened by `truncate_cell_text`, including those
+    /// of union children.
+    fn take_truncated(&self) -> u64 {
+        let children: u64 = self
+            .union_children
+            .iter()
+            .map(|(_, child)| child.take_truncated())
+            .sum();
+        self.truncated.swap(0, Ordering::Relaxed) + children
+    }
+
+    /// Resolves the settings of a column. An [`EXCEL_FORMAT_KEY`] entry in the field metadata
+    /// takes precedence over the default format of the data type, an [`EXCEL_WIDTH_KEY`] entry
+    /// sets the column width unless it is not a number, and [`BINARY_ENCODING_KEY`] and
+    /// [`NUMBER_AS_TEXT_KEY`] entries take precedence over the options unless they are unknown.
+    fn new(
+        field: &Field,
+        options: &WriteOptions,
+        formats: &mut FormatCache,
+    ) -> Result<Self, arrow::error::ArrowError> {
+        let mut spec = Self::from_data_type(field.name(), field.data_type(), options, formats)?;
+        if let Some(num_format) = field.metadata().get(EXCEL_FORMAT_KEY) {
+            spec.format = Some(formats.get(FormatKey::Number(num_format.clone())));
+        }
+        spec.width = field
+            .metadata()
+            .get(EXCEL_WIDTH_KEY)
+            .and_then(|width| width.trim().parse::<f64>().ok())
+            .filter(|width| width.is_finite() && *width >= 0.0);
+        if let Some(encoding) = field
+            .metadata()
+            .get(BINARY_ENCODING_KEY)
+            .and_then(|name| BinaryEncoding::from_name(name))
+        {
+            spec.binary_encoding = encoding;
+        }
+        match field
+            .metadata()
+            .get(NUMBER_AS_TEXT_KEY)
+            .map(|flag| flag.trim())
+        {
+            Some(flag) if flag.eq_ignore_ascii_case("true") => spec.number_as_text = true,
+            Some(flag) if flag.eq_ignore_ascii_case("false") => spec.number_as_text = false,
+            _ => {}
+        }
+        if spec.number_as_text && field.data_type().is_integer() {
+            spec.numeric = false;
+        }
+        Ok(spec)
+    }
+
+    /// Resolves the settings of a column of the named field from its data type.
+    ///
+    /// A timezone that cannot be parsed is logged and ignored, so its timestamps are written as
+    /// naive UTC.
+    fn from_data_type(
+        name: &str,
+        data_type: &DataType,
+        options: &WriteOptions,
+        formats: &mut FormatCache,
+    ) -> Result<Self, arrow::error::ArrowError> {
+        let tz = match data_type {
+            DataType::Timestamp(_, Some(tz)) => match tz.parse::<Tz>() {
+                Ok(tz) => Some(tz),
+                Err(e) => {
+                    log::warn!("column {:?}: {}; writing the timestamps as UTC", name, e);
+                    None
+                }
+            },
+            DataType::Timestamp(_, None) => options.naive_timestamp_tz,
+            DataType::Dictionary(_, value_type) => {
+                return Self::from_data_type(name, value_type, options, formats);
+            }
+            DataType::RunEndEncoded(_, values) => {
+                return Self::from_data_type(name, values.data_type(), options, formats);
+            }
+            _ => None,
+        };
+        let union_children = match data_type {
+            DataType::Union(fields, _) => fields
+                .iter()
+                .map(|(type_id, field)| Ok((type_id, Self::new(field, options, formats)?)))
+                .collect::<Result<_, arrow::error::ArrowError>>()?,
+            _ => Vec::new(),
+        };
+        Ok(Self {
+            format: column_format(data_type, options, formats),
+            tz,
+            numeric: data_type.is_numeric() && !matches!(data_type, DataType::Decimal256(_, _)),
+            union_children,
+            width: None,
+            binary_encoding: options.binary_encoding,
+            number_as_text: options.numbers_as_text,
+            formula: false,
+            truncated: AtomicU64::new(0),
+        })
+    }
+}
+
+/// Converts a timestamp to the wall-clock time of its timezone, or naive UTC if it has none.
+///
+/// `tz` is that of the column, or [`WriteOptionsBuilder::naive_timestamp_tz`] for a column
+/// without one.
+fn timestamp_datetime<T: ArrowTimestampType>(
+    array: &PrimitiveArray<T>,
+    row: usize,
+    tz: Option<Tz>,
+) -> Option<NaiveDateTime> {
+    match tz {
+        Some(tz) => array
+            .value_as_datetime_with_tz(row, tz)
+            .map(|datetime| datetime.naive_local()),
+        None => array.value_as_datetime(row),
+    }
+}
+
+/// Returns the dictionary key at the given row as an index into the dictionary values.
+fn dictionary_key(keys: &dyn Array, key_type: &DataType, row: usize) -> Option<usize> {
+    match key_type {
+        DataType::Int8 => keys.as_primitive::<Int8Type>().value(row).to_usize(),
+        DataType::Int16 => keys.as_primitive::<Int16Type>().value(row).to_usize(),
+        DataType::Int32 => keys.as_primitive::<Int32Type>().value(row).to_usize(),
+        DataType::Int64 => keys.as_primitive::<Int64Type>().value(row).to_usize(),
+        DataType::UInt8 => keys.as_primitive::<UInt8Type>().value(row).to_usize(),
+        DataType::UInt16 => keys.as_primitive::<UInt16Type>().value(row).to_usize(),
+        DataType::UInt32 => keys.as_primitive::<UInt32Type>().value(row).to_usize(),
+        DataType::UInt64 => keys.as_primitive::<UInt64Type>().value(row).to_usize(),
+        _ => None,
+    }
+}
+
+/// Returns whether the values of the data type are written by [`value_writer`] rather than as
+/// an `unsupported data type` placeholder.
+///
+/// It is resolved from the schema, so that [`WriteOptionsBuilder::strict`] fails before any
+/// batch is written, even if every value of a column is null.
+fn is_supported(data_type: &DataType) -> bool {
+    match data_type {
+        DataType::Time32(unit) => matches!(unit, TimeUnit::Second | TimeUnit::Millisecond),
+        DataType::Time64(unit) => matches!(unit, TimeUnit::Microsecond | TimeUnit::Nanosecond),
+        DataType::Dictionary(_, value_type) => is_supported(value_type),
+        DataType::RunEndEncoded(_, values) => is_supported(values.data_type()),
+        DataType::Union(fields, _) => fields
+            .iter()
+            .all(|(_, field)| is_supported(field.data_type())),
+        DataType::Null
+        | DataType::Utf8
+        | DataType::LargeUtf8
+        | DataType::Utf8View
+        | DataType::Binary
+        | DataType::LargeBinary
+        | DataType::FixedSizeBinary(_)
+        | DataType::BinaryView
+        | DataType::Int8
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64
+        | DataType::UInt8
+        | DataType::UInt16
+        | DataType::UInt32
+        | DataType::UInt64
+        | DataType::Float16
+        | DataType::Float32
+        | DataType::Float64
+        | DataType::Decimal128(_, _)
+        | DataType::Decimal256(_, _)
+        | DataType::Boolean
+        | DataType::Date32
+        | DataType::Date64
+        | DataType::Timestamp(_, _)
+        | DataType::Interval(_)
+        | DataType::Duration(_)
+        | DataType::List(_)
+        | DataType::LargeList(_)
+        | DataType::FixedSizeList(_, _)
+        | DataType::Map(_, _) => true,
+        _ => false,
+    }
+}
+
+/// Resolves the [`CellWriter`] of each leaf column of a batch.
+fn cell_writers<'a>(
+    columns: &'a [LeafColumn],
+    specs: &'a [ColumnSpec],
+    options: &'a WriteOptions,
+) -> Vec<CellWriter<'a>> {
+    columns
+        .iter()
+        .zip(specs)
+        .map(|(column, spec)| {
+            let parent_nulls = column.parent_nulls.as_ref();
+            cell_writer(column.array.as_ref(), parent_nulls, spec, options)
+        })
+        .collect()
+}
+
+/// Writes the value at a row of a column to a cell at `(row_offset, col)`.
+///
+/// Returns `false` if the data type is not supported, after writing a placeholder text unless
+/// [`WriteOptionsBuilder::strict`] is enabled.
+type CellWriter<'a> = Box<dyn Fn(&mut Worksheet, u32, u16, usize) -> Result<bool, XlsxError> + 'a>;
+
+/// Boxes a closure as a [`CellWriter`], fixing its signature.
+fn boxed<'a, F>(write: F) -> CellWriter<'a>
+where
+    F: Fn(&mut Worksheet, u32, u16, usize) -> Result<bool, XlsxError> + 'a,
+{
+    Box::new(write)
+}
+
+macro_rules! number_writer {
+    ($column:expr, $format:expr, $type:ty) => {{
+        let array = $column.as_primitive::<$type>();
+        boxed(move |worksheet, row_offset, col, row| {
+            write_number(worksheet, row_offset, col, array.value(row) as f64, $format)?;
+            Ok(true)
+        })
+    }};
+}
+
+macro_rules! wide_integer_writer {
+    ($column:expr, $spec:expr, $options:expr, $type:ty) => {{
+        let array = $column.as_primitive::<$type>();
+        let (spec, options) = ($spec, $options);
+        boxed(move |worksheet, row_offset, col, row| {
+            let value = array.value(row);
+            if value.abs_diff(0) <= MAX_EXACT_F64_INT {
+                let format = spec.format.as_deref();
+                write_number(worksheet, row_offset, col, value as f64, format)?;
+            } else {
+                let text = value.to_string();
+                write_cell_text(worksheet, row_offset, col, &text, spec, options)?;
+            }
+            Ok(true)
+        })
+    }};
+}
+
+macro_rules! binary_writer {
+    ($array:expr, $spec:expr, $options:expr) => {{
+        let array = $array;
+        let (spec, options) = ($spec, $options);
+        boxed(move |worksheet, row_offset, col, row| {
+            let value = spec.binary_encoding.encode(array.value(row));
+            write_cell_text(worksheet, row_offset, col, &value, spec, options)?;
+            Ok(true)
+        })
+    }};
+}
+
+/// Resolves how the cells of a column are written, once for all of its rows, so that the data
+/// type is not matched again for every cell.
+///
+/// `parent_nulls` is the validity of an enclosing struct that is not in the array itself, see
+/// [`LeafColumn`].
+fn cell_writer<'a>(
+    column: &'a dyn Array,
+    parent_nulls: Option<&'a NullBuffer>,
+    spec: &'a ColumnSpec,
+    options: &'a WriteOptions,
+) -> CellWriter<'a> {
+    let write_value = value_writer(column, spec, options);
+    // Null-typed arrays have no validity buffer, so `is_null` is false for their rows.
+    let null_typed = column.data_type() == &DataType::Null;
+    let custom = options.cell_writer.as_ref();
+    boxed(move |worksheet, row_offset, col, row| {
+        let parent_null = parent_nulls.is_some_and(|nulls| nulls.is_null(row));
+        if !parent_null
+            && let Some(custom) = custom
+            && (custom.0)(worksheet, row_offset, col, column, row)?
+        {
+            return Ok(true);
+        }
+        if parent_null || null_typed || column.is_null(row) {
+            write_null(worksheet, row_offset, col, options)?;
+            return Ok(true);
+        }
+        write_value(worksheet, row_offset, col, row)
+    })
+}
+
+/// Writes a null value as [`WriteOptionsBuilder::null_text`], or leaves the cell empty.
+fn write_null(
+    worksheet: &mut Worksheet,
+    row: u32,
+    col: u16,
+    options: &WriteOptions,
+) -> Result<(), XlsxError> {
+    if let Some(null_text) = &options.null_text {
+        worksheet.write_string(row, col, null_text)?;
+    }
+    Ok(())
+}
+
+/// Resolves how the non-null values of a column are written.
+fn value_writer<'a>(
+    column: &'a dyn Array,
+    spec: &'a ColumnSpec,
+    options: &'a WriteOptions,
+) -> CellWriter<'a> {
+    let format = spec.format.as_deref();
+
+    if spec.number_as_text
+        && column.data_type().is_integer()
+        && let Ok(formatter) = ArrayFormatter::try_new(column, &FormatOptions::default())
+    {
+        return boxed(move |worksheet, row_offset, col, row| {
+            let text = formatter.value(row).to_string();
+            write_cell_text(worksheet, row_offset, col, &text, spec, options)?;
+            Ok(true)
+        });
+    }
+
+    match column.data_type() {
+        DataType::Null => boxed(|_, _, _, _| Ok(true)),
+        DataType::Utf8 => {
+            let array = column.as_string::<i32>();
+            boxed(move |worksheet, row_offset, col, row| {
+                write_text(worksheet, row_offset, col, array.value(row), spec, options)?;
+                Ok(true)
+            })
+        }
+        DataType::LargeUtf8 => {
+            let array = column.as_string::<i64>();
+            boxed(move |worksheet, row_offset, col, row| {
+                write_text(worksheet, row_offset, col, array.value(row), spec, options)?;
+                Ok(true)
+            })
+        }
+        DataType::Utf8View => {
+            let array = column.as_string_view();
+            boxed(move |worksheet, row_offset, col, row| {
+                write_text(worksheet, row_offset, col, array.value(row), spec, options)?;
+                Ok(true)
+            })
+        }
+        DataType::Binary => binary_writer!(column.as_binary::<i32>(), spec, options),
+        DataType::LargeBinary => binary_writer!(column.as_binary::<i64>(), spec, options),
+        DataType::FixedSizeBinary(_) => {
+            binary_writer!(column.as_fixed_size_binary(), spec, options)
+        }
+        DataType::BinaryView => binary_writer!(column.as_binary_view(), spec, options),
+        DataType::Int8 => number_writer!(column, format, Int8Type),
+        DataType::Int16 => number_writer!(column, format, Int16Type),
+        DataType::Int32 => number_writer!(column, format, Int32Type),
+        DataType::Int64 => wide_integer_writer!(column, spec, options, Int64Type),
+        DataType::UInt8 => number_writer!(column, format, UInt8Type),
+        DataType::UInt16 => number_writer!(column, format, UInt16Type),
+        DataType::UInt32 => number_writer!(column, format, UInt32Type),
+        DataType::UInt64 => wide_integer_writer!(column, spec, options, UInt64Type),
+        DataType::Float16 => {
+            let array = column.as_primitive::<Float16Type>();
+            boxed(move |worksheet, row_offset, col, row| {
+                // Every half-precision value, subnormals, NaN and infinities included, is exactly
+                // representable as an f64, so the conversion needs no rounding.
+                let value = array.value(row).to_f64();
+                write_float(worksheet, row_offset, col, value, spec, options)?;
+                Ok(true)
+            })
+        }
+        DataType::Float32 => {
+            let array = column.as_primitive::<Float32Type>();
+            boxed(move |worksheet, row_offset, col, row| {
+                let value = f64::from(array.value(row));
+                write_float(worksheet, row_offset, col, value, spec, options)?;
+                Ok(true)
+            })
+        }
+        DataType::Float64 => {
+            let array = column.as_primitive::<Float64Type>();
+            boxed(move |worksheet, row_offset, col, row| {
+                write_float(worksheet, row_offset, col, array.value(row), spec, options)?;
+                Ok(true)
+            })
+        }
+        DataType::Decimal128(precision, scale) => {
+            let array = column.as_primitive::<Decimal128Type>();
+            let (precision, scale) = (*precision, *scale);
+            let divisor = 10f64.powi(scale as i32);
+            boxed(move |worksheet, row_offset, col, row| {
+                let value = array.value(row);
+                if value.unsigned_abs() <= u128::from(MAX_EXACT_F64_INT) {
+                    write_number(worksheet, row_offset, col, value as f64 / divisor, format)?;
+                } else {
+                    let s = Decimal128Type::format_decimal(value, precision, scale);
+                    write_cell_text(worksheet, row_offset, col, &s, spec, options)?;
+                }
+                Ok(true)
+            })
+        }
+        DataType::Decimal256(precision, scale) => {
+            let array = column.as_primitive::<Decimal256Type>();
+            let (precision, scale) = (*precision, *scale);
+            boxed(move |worksheet, row_offset, col, row| {
+                let s = Decimal256Type::format_decimal(array.value(row), precision, scale);
+                write_cell_text(worksheet, row_offset, col, &s, spec, options)?;
+                Ok(true)
+            })
+        }
+        DataType::Boolean if options.bool_as_text => {
+            let array = column.as_boolean();
+            let (true_text, false_text) = &options.bool_labels;
+            boxed(move |worksheet, row_offset, col, row| {
+                let text = if array.value(row) {
+                    true_text
+                } else {
+                    false_text
+                };
+                write_cell_text(worksheet, row_offset, col, text, spec, options)?;
+                Ok(true)
+            })
+        }
+        DataType::Boolean => {
+            let array = column.as_boolean();
+            boxed(move |worksheet, row_offset, col, row| {
+                let value = array.value(row);
+                match format {
+                    Some(format) => {
+                        worksheet.write_boolean_with_format(row_offset, col, value, format)?
+                    }
+                    None => worksheet.write_boolean(row_offset, col, value)?,
+                };
+                Ok(true)
+            })
+        }
+        DataType::Date32 => {
+            let array = column.as_primitive::<Date32Type>();
+            boxed(move |worksheet, row_offset, col, row| {
+                let value = array.value(row);
+                match NaiveDate::from_epoch_days(value) {
+                    Some(date) => write_date(worksheet, row_offset, col, date, spec, options)?,
+                    None => {
+                        let text = format!("out of range date: {value} days");
+                        write_cell_text(worksheet, row_offset, col, &text, spec, options)?;
+                    }
+                }
+                Ok(true)
+            })
+        }
+        DataType::Date64 => {
+            let array = column.as_primitive::<Date64Type>();
+            boxed(move |worksheet, row_offset, col, row| {
+                let value = array.value(row);
+                match DateTime::<Utc>::from_timestamp_millis(value) {
+                    Some(datetime_utc) => {
+                        let datetime = datetime_utc.naive_utc();
+                        write_date(worksheet, row_offset, col, datetime, spec, options)?;
+                    }
+                    None => {
+                        let text = format!("out of range date: {value} ms");
+                        write_cell_text(worksheet, row_offset, col, &text, spec, options)?;
+                    }
+                }
+                Ok(true)
+            })
+        }
+        // Time32 with a sub-second unit or Time64 with a coarser one are invalid, and fall
+        // through to the unsupported placeholder below rather than leaving the cells empty.
+        DataType::Time32(TimeUnit::Second) => {
+            time_writer::<Time32SecondType>(column, spec, options, "s")
+        }
+        DataType::Time32(TimeUnit::Millisecond) => {
+            time_writer::<Time32MillisecondType>(column, spec, options, "ms")
+        }
+        DataType::Time64(TimeUnit::Microsecond) => {
+            time_writer::<Time64MicrosecondType>(column, spec, options, "us")
+        }
+        DataType::Time64(TimeUnit::Nanosecond) => {
+            time_writer::<Time64NanosecondType>(column, spec, options, "ns")
+        }
+        DataType::Timestamp(unit, _) => match unit {
+            TimeUnit::Second => timestamp_writer::<TimestampSecondType>(column, spec, options, "s"),
+            TimeUnit::Millisecond => {
+                timestamp_writer::<TimestampMillisecondType>(column, spec, options, "ms")
+            }
+            TimeUnit::Microsecond => {
+                timestamp_writer::<TimestampMicrosecondType>(column, spec, options, "us")
+            }
+            TimeUnit::Nanosecond => {
+                timestamp_writer::<TimestampNanosecondType>(column, spec, options, "ns")
+            }
+        },
+        DataType::Interval(IntervalUnit::YearMonth) => {
+            let array = column.as_primitive::<IntervalYearMonthType>();
+            boxed(move |worksheet, row_offset, col, row| {
+                let text = format_year_month(array.value(row));
+                write_cell_text(worksheet, row_offset, col, &text, spec, options)?;
+                Ok(true)
+            })
+        }
+        DataType::Interval(IntervalUnit::DayTime) => {
+            let array = column.as_primitive::<IntervalDayTimeType>();
+            boxed(move |worksheet, row_offset, col, row| {
+                let value = array.value(row);
+                let nanos = i64::from(value.milliseconds) * 1_000_000;
+                let text = format!("{}d {}", value.days, format_clock(nanos));
+                write_cell_text(worksheet, row_offset, col, &text, spec, options)?;
+                Ok(true)
+            })
+        }
+        DataType::Interval(IntervalUnit::MonthDayNano) => {
+            let array = column.as_primitive::<IntervalMonthDayNanoType>();
+            boxed(move |worksheet, row_offset, col, row| {
+                let value = array.value(row);
+                let text = format!(
+                    "{} {}d {}",
+                    format_year_month(value.months),
+                    value.days,
+                    format_clock(value.nanoseconds)
+                );
+                write_cell_text(worksheet, row_offset, col, &text, spec, options)?;
+                Ok(true)
+            })
+        }
+        DataType::Duration(unit) => match unit {
+            TimeUnit::Second => {
+                duration_writer::<DurationSecondType>(column, "s", 1.0, spec, options)
+            }
+            TimeUnit::Millisecond => {
+                duration_writer::<DurationMillisecondType>(column, "ms", 1e3, spec, options)
+            }
+            TimeUnit::Microsecond => {
+                duration_writer::<DurationMicrosecondType>(column, "us", 1e6, spec, options)
+            }
+            TimeUnit::Nanosecond => {
+                duration_writer::<DurationNanosecondType>(column, "ns", 1e9, spec, options)
+            }
+        },
+        DataType::List(_)
+        | DataType::LargeList(_)
+        | DataType::FixedSizeList(_, _)
+        | DataType::Map(_, _) => boxed(move |worksheet, row_offset, col, row| {
+            let mut json = String::new();
+            push_json_value(&mut json, column, row);
+            write_cell_text(worksheet, row_offset, col, &json, spec, options)?;
+            Ok(true)
+        }),
+        DataType::Dictionary(key_type, _) => {
+            let dictionary = column.as_any_dictionary();
+            let keys = dictionary.keys();
+            let write_value = cell_writer(dictionary.values().as_ref(), None, spec, options);
+            boxed(move |worksheet, row_offset, col, row| {
+                match dictionary_key(keys, key_type, row) {
+                    Some(key) => write_value(worksheet, row_offset, col, key),
+                    None => Ok(true),
+                }
+            })
+        }
+        DataType::RunEndEncoded(run_ends, _) => match run_ends.data_type() {
+            DataType::Int16 => run_end_writer::<Int16Type>(column, spec, options),
+            DataType::Int32 => run_end_writer::<Int32Type>(column, spec, options),
+            _ => run_end_writer::<Int64Type>(column, spec, options),
+        },
+        DataType::Union(_, _) => {
+            let union = column.as_union();
+            let children: Vec<(i8, CellWriter<'a>)> = spec
+                .union_children
+                .iter()
+                .map(|(type_id, child_spec)| {
+                    let child = union.child(*type_id).as_ref();
+                    (*type_id, cell_writer(child, None, child_spec, options))
+                })
+                .collect();
+            boxed(move |worksheet, row_offset, col, row| {
+                let type_id = union.type_id(row);
+                match children
+                    .iter()
+                    .find(|(child_type_id, _)| *child_type_id == type_id)
+                {
+                    Some((_, write_child)) => {
+                        write_child(worksheet, row_offset, col, union.value_offset(row))
+                    }
+                    None => Ok(true),
+                }
+            })
+        }
+        data_type => boxed(move |worksheet, row_offset, col, _| {
+            if !options.strict {
+                worksheet.write_string(
+                    row_offset,
+                    col,
+                    format!("unsupported data type: {:?}", data_type),
+                )?;
+            }
+            Ok(false)
+        }),
+    }
+}
+
+/// Writes the values of a run-end encoded column with the writer of its values, looking up the
+/// run of each row rather than expanding the runs.
+fn run_end_writer<'a, R: RunEndIndexType>(
+    column: &'a dyn Array,
+    spec: &'a ColumnSpec,
+    options: &'a WriteOptions,
+) -> CellWriter<'a> {
+    let array = column.as_run::<R>();
+    let write_value = cell_writer(array.values().as_ref(), None, spec, options);
+    boxed(move |worksheet, row_offset, col, row| {
+        write_value(worksheet, row_offset, col, array.get_physical_index(row))
+    })
+}
+
+/// Writes the values of a time column, or a text naming the value and `unit` for those outside
+/// of a day.
+fn time_writer<'a, T>(
+    column: &'a dyn Array,
+    spec: &'a ColumnSpec,
+    options: &'a WriteOptions,
+    unit: &'static str,
+) -> CellWriter<'a>
+where
+    T: ArrowTemporalType,
+    i64: From<T::Native>,
+{
+    let array = column.as_primitive::<T>();
+    boxed(move |worksheet, row_offset, col, row| {
+        match array.value_as_time(row) {
+            Some(time) => write_datetime(worksheet, row_offset, col, time, spec.format.as_deref())?,
+            None => {
+                let value = i64::from(array.value(row));
+                let text = format!("out of range time: {value} {unit}");
+                write_cell_text(worksheet, row_offset, col, &text, spec, options)?;
+            }
+        }
+        Ok(true)
+    })
+}
+
+/// Writes the values of a timestamp column, or a text naming the value and `unit` for those
+/// outside of the dates chrono can represent.
+fn timestamp_writer<'a, T: ArrowTimestampType>(
+    column: &'a dyn Array,
+    spec: &'a ColumnSpec,
+    options: &'a WriteOptions,
+    unit: &'static str,
+) -> CellWriter<'a> {
+    let array = column.as_primitive::<T>();
+    boxed(move |worksheet, row_offset, col, row| {
+        match timestamp_datetime(array, row, spec.tz) {
+            Some(datetime) => {
+                let format = spec.format.as_deref();
+                write_datetime(worksheet, row_offset, col, datetime, format)?;
+            }
+            None => {
+                let text = format!("out of range timestamp: {} {unit}", array.value(row));
+                write_cell_text(worksheet, row_offset, col, &text, spec, options)?;
+            }
+        }
+        Ok(true)
+    })
+}
+
+fn duration_writer<'a, T: ArrowPrimitiveType<Native = i64>>(
+    column: &'a dyn Array,
+    suffix: &'static str,
+    per_second: f64,
+    spec: &'a ColumnSpec,
+    options: &'a WriteOptions,
+) -> CellWriter<'a> {
+    let format = spec.format.as_deref();
+    let array = column.as_primitive::<T>();
+    if options.duration_as_seconds {
+        return boxed(move |worksheet, row_offset, col, row| {
+            let seconds = array.value(row) as f64 / per_second;
+            write_number(worksheet, row_offset, col, seconds, format)?;
+            Ok(true)
+        });
+    }
+    boxed(move |worksheet, row_offset, col, row| {
+        let text = format!("{}{suffix}", array.value(row));
+        write_cell_text(worksheet, row_offset, col, &text, spec, options)?;
+        Ok(true)
+    })
+}
+
+#[cfg(test)]
+mod tests;
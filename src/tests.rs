@@ -0,0 +1,2516 @@
+use std::io::Cursor;
+
+use arrow::array::*;
+use arrow::buffer::OffsetBuffer;
+use calamine::{Data, Reader, Xlsx};
+
+use super::*;
+
+/// Builds a batch of the named columns, nullable if they have nulls.
+fn batch(columns: Vec<(&str, ArrayRef)>) -> RecordBatch {
+    RecordBatch::try_from_iter(columns).unwrap()
+}
+
+/// Writes the batches to a worksheet named `S` and returns the saved workbook and the summary.
+fn write(batches: Vec<RecordBatch>, options: &WriteOptions) -> (Vec<u8>, WriteSummary) {
+    let mut book = Workbook::new();
+    let summary =
+        batch_iter2x_with_options(batches.into_iter().map(Ok), &mut book, "S", options).unwrap();
+    (book.save_to_buffer().unwrap(), summary)
+}
+
+/// Writes the batches with the default options and returns the cells of the worksheet.
+fn write_cells(batches: Vec<RecordBatch>) -> Vec<Vec<Data>> {
+    let (xlsx, _) = write(batches, &WriteOptions::default());
+    cells(&xlsx)
+}
+
+/// Returns the names and cells of the worksheets of a saved workbook, in order.
+fn sheets(xlsx: &[u8]) -> Vec<(String, Vec<Vec<Data>>)> {
+    let mut book: Xlsx<_> = Xlsx::new(Cursor::new(xlsx)).unwrap();
+    book.sheet_names()
+        .into_iter()
+        .map(|name| {
+            let range = book.worksheet_range(&name).unwrap();
+            let rows = match range.end() {
+                Some((last_row, last_col)) => (0..=last_row)
+                    .map(|row| {
+                        (0..=last_col)
+                            .map(|col| range.get_value((row, col)).cloned().unwrap_or_default())
+                            .collect()
+                    })
+                    .collect(),
+                None => Vec::new(),
+            };
+            (name, rows)
+        })
+        .collect()
+}
+
+/// Returns the cells of the first worksheet of a saved workbook, from `A1` on.
+fn cells(xlsx: &[u8]) -> Vec<Vec<Data>> {
+    sheets(xlsx).swap_remove(0).1
+}
+
+/// Returns a part of a saved workbook, such as `xl/worksheets/sheet1.xml`.
+fn xml(xlsx: &[u8], part: &str) -> String {
+    let mut archive = zip::ZipArchive::new(Cursor::new(xlsx)).unwrap();
+    std::io::read_to_string(archive.by_name(part).unwrap()).unwrap()
+}
+
+/// Returns the value of an attribute of the first tag of an XML text.
+fn attribute<'x>(tag: &'x str, name: &str) -> Option<&'x str> {
+    let tag = &tag[..tag.find('>')?];
+    let start = tag.find(&format!(" {name}=\""))? + name.len() + 3;
+    tag[start..].split('"').next()
+}
+
+/// Returns the `xf` style record of a cell of the first worksheet, or `None` if it has none.
+fn cell_style(xlsx: &[u8], cell: &str) -> Option<String> {
+    let sheet = xml(xlsx, "xl/worksheets/sheet1.xml");
+    let tag = &sheet[sheet.find(&format!("<c r=\"{cell}\""))?..];
+    let style: usize = attribute(tag, "s")?.parse().unwrap();
+    let styles = xml(xlsx, "xl/styles.xml");
+    let cell_xfs = &styles[styles.find("<cellXfs").unwrap()..];
+    let xf = cell_xfs.split("<xf ").nth(style + 1).unwrap();
+    Some(format!("<xf {xf}"))
+}
+
+/// Returns the number format of a cell of the first worksheet, such as `0.00`, or `None` if it
+/// has the general format.
+fn num_format(xlsx: &[u8], cell: &str) -> Option<String> {
+    let xf = cell_style(xlsx, cell)?;
+    let id = attribute(&xf, "numFmtId")?;
+    let styles = xml(xlsx, "xl/styles.xml");
+    let num_fmt = &styles[styles.find(&format!("<numFmt numFmtId=\"{id}\""))?..];
+    attribute(num_fmt, "formatCode").map(str::to_string)
+}
+
+/// Returns the `font` record of a cell of the first worksheet.
+fn font(xlsx: &[u8], cell: &str) -> String {
+    let font_id: usize = cell_style(xlsx, cell)
+        .and_then(|xf| attribute(&xf, "fontId")?.parse().ok())
+        .unwrap_or(0);
+    let styles = xml(xlsx, "xl/styles.xml");
+    let fonts = &styles[styles.find("<fonts").unwrap()..styles.find("</fonts>").unwrap()];
+    fonts.split("<font>").nth(font_id + 1).unwrap().to_string()
+}
+
+#[test]
+fn decimal128_is_a_number_with_the_scale_as_format() {
+    let decimals = Decimal128Array::from(vec![Some(12_345_678), None, Some(-5)])
+        .with_precision_and_scale(10, 4)
+        .unwrap();
+    let (xlsx, _) = write(
+        vec![batch(vec![("d", Arc::new(decimals))])],
+        &WriteOptions::default(),
+    );
+    assert_eq!(
+        cells(&xlsx),
+        [
+            vec![Data::String("d".into())],
+            vec![Data::Float(1234.5678)],
+            vec![Data::Empty],
+            vec![Data::Float(-0.0005)],
+        ]
+    );
+    assert_eq!(num_format(&xlsx, "A2").as_deref(), Some("0.0000"));
+}
+
+#[test]
+fn decimal256_keeps_every_digit_as_text() {
+    let value = i256::from_string("123456789012345678901234567890").unwrap();
+    let decimals = Decimal256Array::from(vec![value, i256::from_i128(-15)])
+        .with_precision_and_scale(40, 10)
+        .unwrap();
+    assert_eq!(
+        write_cells(vec![batch(vec![("d", Arc::new(decimals))])]),
+        [
+            vec![Data::String("d".into())],
+            vec![Data::String("12345678901234567890.1234567890".into())],
+            vec![Data::String("-0.0000000015".into())],
+        ]
+    );
+}
+
+/// Returns the serial number of a date or datetime cell.
+fn serial(cell: &Data) -> f64 {
+    match cell {
+        Data::DateTime(datetime) => datetime.as_f64(),
+        cell => panic!("not a datetime: {cell:?}"),
+    }
+}
+
+#[test]
+fn timestamp_is_a_datetime_with_milliseconds() {
+    let timestamps = TimestampMillisecondArray::from(vec![1_700_000_000_123]);
+    let (xlsx, _) = write(
+        vec![batch(vec![("t", Arc::new(timestamps))])],
+        &WriteOptions::default(),
+    );
+    let expected = 25_569.0 + 1_700_000_000_123.0 / 86_400_000.0;
+    assert!((serial(&cells(&xlsx)[1][0]) - expected).abs() < 1e-9);
+    assert_eq!(
+        num_format(&xlsx, "A2").as_deref(),
+        Some("yyyy-mm-dd hh:mm:ss.000")
+    );
+}
+
+#[test]
+fn timestamp_with_timezone_is_local_time() {
+    // 2024-01-15 12:00:00 UTC is 07:00 in New York.
+    let timestamps =
+        TimestampSecondArray::from(vec![1_705_320_000]).with_timezone("America/New_York");
+    let cells = write_cells(vec![batch(vec![("t", Arc::new(timestamps))])]);
+    let expected = 45_306.0 + 7.0 / 24.0;
+    assert!((serial(&cells[1][0]) - expected).abs() < 1e-9);
+}
+
+#[test]
+fn timestamp_with_an_unknown_timezone_is_utc() {
+    let unknown = TimestampSecondArray::from(vec![1_705_320_000]).with_timezone("Mars/Olympus");
+    let known = TimestampSecondArray::from(vec![1_705_320_000]).with_timezone("Asia/Tokyo");
+    let cells = write_cells(vec![batch(vec![
+        ("mars", Arc::new(unknown)),
+        ("tokyo", Arc::new(known)),
+    ])]);
+    assert!((serial(&cells[1][0]) - (45_306.0 + 12.0 / 24.0)).abs() < 1e-9);
+    assert!((serial(&cells[1][1]) - (45_306.0 + 21.0 / 24.0)).abs() < 1e-9);
+}
+
+#[test]
+fn times_are_time_formatted_fractions_of_a_day() {
+    let seconds = Time32SecondArray::from(vec![3_661]);
+    let nanos = Time64NanosecondArray::from(vec![43_200_500_000_000]);
+    let (xlsx, _) = write(
+        vec![batch(vec![
+            ("s", Arc::new(seconds)),
+            ("ns", Arc::new(nanos)),
+        ])],
+        &WriteOptions::default(),
+    );
+    let cells = cells(&xlsx);
+    assert!((serial(&cells[1][0]) - 3_661.0 / 86_400.0).abs() < 1e-12);
+    assert!((serial(&cells[1][1]) - 43_200.5 / 86_400.0).abs() < 1e-12);
+    assert_eq!(num_format(&xlsx, "A2").as_deref(), Some("hh:mm:ss"));
+    assert_eq!(num_format(&xlsx, "B2").as_deref(), Some("hh:mm:ss.000"));
+}
+
+#[test]
+fn wide_integers_beyond_2_pow_53_are_exact_text() {
+    let signed = Int64Array::from(vec![i64::MAX, 42]);
+    let unsigned = UInt64Array::from(vec![u64::MAX, 1 << 53]);
+    assert_eq!(
+        write_cells(vec![batch(vec![
+            ("i", Arc::new(signed)),
+            ("u", Arc::new(unsigned)),
+        ])]),
+        [
+            vec![Data::String("i".into()), Data::String("u".into())],
+            vec![
+                Data::String("9223372036854775807".into()),
+                Data::String("18446744073709551615".into()),
+            ],
+            vec![Data::Float(42.0), Data::Float(9_007_199_254_740_992.0)],
+        ]
+    );
+}
+
+#[test]
+fn large_utf8_is_written_and_shortened_to_the_cell_limit() {
+    let long = "a".repeat(MAX_CELL_CHARS + 10);
+    let strings = LargeStringArray::from(vec![Some("x"), None, Some(long.as_str())]);
+    let cells = write_cells(vec![batch(vec![("s", Arc::new(strings))])]);
+    assert_eq!(cells[1][0], Data::String("x".into()));
+    assert_eq!(cells[2][0], Data::Empty);
+    let Data::String(text) = &cells[3][0] else {
+        panic!("not a string: {:?}", cells[3][0]);
+    };
+    assert_eq!(text.chars().count(), MAX_CELL_CHARS);
+    assert!(text.ends_with("a…"));
+}
+
+#[test]
+fn view_types_are_written_like_their_plain_types() {
+    let strings = StringViewArray::from(vec!["short", "a string longer than twelve bytes"]);
+    let binaries = BinaryViewArray::from(vec![&b"\xde\xad"[..], &[0x01; 13][..]]);
+    assert_eq!(
+        write_cells(vec![batch(vec![
+            ("s", Arc::new(strings)),
+            ("b", Arc::new(binaries)),
+        ])]),
+        [
+            vec![Data::String("s".into()), Data::String("b".into())],
+            vec![Data::String("short".into()), Data::String("dead".into())],
+            vec![
+                Data::String("a string longer than twelve bytes".into()),
+                Data::String("01".repeat(13)),
+            ],
+        ]
+    );
+}
+
+#[test]
+fn dictionary_values_are_decoded() {
+    let strings: DictionaryArray<Int32Type> = vec![Some("b"), Some("a"), None, Some("b")]
+        .into_iter()
+        .collect();
+    let numbers = DictionaryArray::<UInt8Type>::try_new(
+        UInt8Array::from(vec![1, 0, 1, 1]),
+        Arc::new(Int64Array::from(vec![10, 20])),
+    )
+    .unwrap();
+    assert_eq!(
+        write_cells(vec![batch(vec![
+            ("s", Arc::new(strings)),
+            ("n", Arc::new(numbers)),
+        ])]),
+        [
+            vec![Data::String("s".into()), Data::String("n".into())],
+            vec![Data::String("b".into()), Data::Float(20.0)],
+            vec![Data::String("a".into()), Data::Float(10.0)],
+            vec![Data::Empty, Data::Float(20.0)],
+            vec![Data::String("b".into()), Data::Float(20.0)],
+        ]
+    );
+}
+
+#[test]
+fn lists_are_json_arrays() {
+    let list = ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+        Some(vec![Some(1), None, Some(3)]),
+        Some(vec![]),
+        None,
+    ]);
+    let large = LargeListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+        Some(vec![Some(4)]),
+        Some(vec![Some(5), Some(6)]),
+        Some(vec![]),
+    ]);
+    let field = Arc::new(Field::new_list_field(list.data_type().clone(), true));
+    let nested = ListArray::new(
+        field,
+        OffsetBuffer::from_lengths([2, 0, 1]),
+        Arc::new(list.clone()),
+        None,
+    );
+    assert_eq!(
+        write_cells(vec![batch(vec![
+            ("l", Arc::new(list)),
+            ("ll", Arc::new(large)),
+            ("n", Arc::new(nested)),
+        ])]),
+        [
+            vec![
+                Data::String("l".into()),
+                Data::String("ll".into()),
+                Data::String("n".into()),
+            ],
+            vec![
+                Data::String("[1,null,3]".into()),
+                Data::String("[4]".into()),
+                Data::String("[[1,null,3],[]]".into()),
+            ],
+            vec![
+                Data::String("[]".into()),
+                Data::String("[5,6]".into()),
+                Data::String("[]".into()),
+            ],
+            vec![
+                Data::Empty,
+                Data::String("[]".into()),
+                Data::String("[null]".into())
+            ],
+        ]
+    );
+}
+
+#[test]
+fn struct_fields_are_flattened_into_columns() {
+    let point = StructArray::from(vec![
+        (
+            Arc::new(Field::new("x", DataType::Int32, false)),
+            Arc::new(Int32Array::from(vec![1, 2])) as ArrayRef,
+        ),
+        (
+            Arc::new(Field::new("y", DataType::Utf8, false)),
+            Arc::new(StringArray::from(vec!["a", "b"])) as ArrayRef,
+        ),
+    ]);
+    assert_eq!(
+        write_cells(vec![batch(vec![("p", Arc::new(point))])]),
+        [
+            vec![Data::String("p.x".into()), Data::String("p.y".into())],
+            vec![Data::Float(1.0), Data::String("a".into())],
+            vec![Data::Float(2.0), Data::String("b".into())],
+        ]
+    );
+}
+
+#[test]
+fn options_suppress_the_header_and_set_the_null_text() {
+    let options = WriteOptions::builder()
+        .write_header(false)
+        .null_text("N/A")
+        .build();
+    let (xlsx, _) = write(
+        vec![batch(vec![(
+            "n",
+            Arc::new(Int32Array::from(vec![Some(1), None])),
+        )])],
+        &options,
+    );
+    assert_eq!(
+        cells(&xlsx),
+        [vec![Data::Float(1.0)], vec![Data::String("N/A".into())]]
+    );
+}
+
+/// Returns the names of the worksheets of a saved workbook.
+fn sheet_names(xlsx: &[u8]) -> Vec<String> {
+    let book: Xlsx<_> = Xlsx::new(Cursor::new(xlsx)).unwrap();
+    book.sheet_names()
+}
+
+#[test]
+fn rows_beyond_the_sheet_limit_continue_on_a_new_sheet() {
+    let half = 600_000;
+    let numbers = |start: i32| Arc::new(Int32Array::from_iter_values(start..start + half));
+    let batches = vec![
+        batch(vec![("n", numbers(0))]),
+        batch(vec![("n", numbers(half))]),
+    ];
+    let (xlsx, summary) = write(batches, &WriteOptions::default());
+    assert_eq!(summary.rows_written, 1_200_000);
+    assert_eq!(summary.sheets_written, 2);
+    assert_eq!(sheet_names(&xlsx), ["S", "S (2)"]);
+    let mut book: Xlsx<_> = Xlsx::new(Cursor::new(&xlsx)).unwrap();
+    let first = book.worksheet_range("S").unwrap();
+    assert_eq!(first.end(), Some((MAX_ROWS - 1, 0)));
+    assert_eq!(
+        first.get_value((MAX_ROWS - 1, 0)),
+        Some(&Data::Float(1_048_574.0))
+    );
+    let second = book.worksheet_range("S (2)").unwrap();
+    assert_eq!(second.get_value((0, 0)), Some(&Data::String("n".into())));
+    assert_eq!(second.get_value((1, 0)), Some(&Data::Float(1_048_575.0)));
+    assert_eq!(second.end(), Some((1_200_000 - 1_048_575, 0)));
+}
+
+#[test]
+fn schemas_wider_than_the_sheet_limit_fail() {
+    let fields: Vec<Field> = (0..20_000)
+        .map(|i| Field::new(format!("c{i}"), DataType::Int8, true))
+        .collect();
+    let mut book = Workbook::new();
+    let result = batch_iter2x_with_schema(
+        std::iter::empty(),
+        &Schema::new(fields),
+        &mut book,
+        "S",
+        &WriteOptions::default(),
+    );
+    assert!(matches!(
+        result,
+        Err(Error::TooManyColumns {
+            found: 20_000,
+            max: MAX_COLS,
+        })
+    ));
+}
+
+/// Encodes the batches as an Arrow IPC stream with the given schema.
+fn ipc_stream(schema: &Schema, batches: &[RecordBatch]) -> Vec<u8> {
+    let mut writer = arrow::ipc::writer::StreamWriter::try_new(Vec::new(), schema).unwrap();
+    for batch in batches {
+        writer.write(batch).unwrap();
+    }
+    writer.into_inner().unwrap()
+}
+
+#[test]
+fn empty_stream_writes_the_header_row() {
+    let schema = Schema::new(vec![
+        Field::new("a", DataType::Int32, true),
+        Field::new("b", DataType::Utf8, true),
+    ]);
+    let stream = ipc_stream(&schema, &[]);
+    let reader = StreamReader::try_new(Cursor::new(stream), None).unwrap();
+    let mut book = Workbook::new();
+    reader2x(reader, &mut book, "S").unwrap();
+    assert_eq!(
+        cells(&book.save_to_buffer().unwrap()),
+        [vec![Data::String("a".into()), Data::String("b".into())]]
+    );
+}
+
+#[test]
+fn batches_with_another_schema_fail() {
+    let batches = vec![
+        Ok(batch(vec![("a", Arc::new(Int32Array::from(vec![1])))])),
+        Ok(batch(vec![("a", Arc::new(StringArray::from(vec!["x"])))])),
+    ];
+    let mut book = Workbook::new();
+    let result = batch_iter2x(batches.into_iter(), &mut book, "S");
+    let Err(Error::SchemaMismatch { expected, found }) = result else {
+        panic!("not a schema mismatch: {result:?}");
+    };
+    assert_eq!(expected.field(0).data_type(), &DataType::Int32);
+    assert_eq!(found.field(0).data_type(), &DataType::Utf8);
+}
+
+/// Writes a batch of a single integer column with the options and returns the saved workbook.
+fn write_numbers(options: &WriteOptions) -> Vec<u8> {
+    let numbers = Int32Array::from(vec![1, 2, 3]);
+    write(vec![batch(vec![("n", Arc::new(numbers))])], options).0
+}
+
+#[test]
+fn header_row_is_frozen_unless_disabled() {
+    let sheet = xml(
+        &write_numbers(&WriteOptions::default()),
+        "xl/worksheets/sheet1.xml",
+    );
+    assert!(sheet.contains(r#"<pane ySplit="1" topLeftCell="A2""#));
+    assert!(sheet.contains(r#"state="frozen""#));
+
+    let options = WriteOptions::builder().freeze_header(false).build();
+    let sheet = xml(&write_numbers(&options), "xl/worksheets/sheet1.xml");
+    assert!(!sheet.contains("<pane"));
+}
+
+#[test]
+fn header_row_has_the_header_format() {
+    let sheet = write_numbers(&WriteOptions::default());
+    assert!(font(&sheet, "A1").contains("<b/>"));
+    assert!(!font(&sheet, "A2").contains("<b/>"));
+
+    let options = WriteOptions::builder()
+        .header_format(Some(Format::new().set_italic()))
+        .build();
+    let sheet = write_numbers(&options);
+    assert!(font(&sheet, "A1").contains("<i/>"));
+    assert!(!font(&sheet, "A1").contains("<b/>"));
+
+    let options = WriteOptions::builder().header_format(None).build();
+    assert_eq!(cell_style(&write_numbers(&options), "A1"), None);
+}
+
+/// Returns the width of a column of the first worksheet, by its 1-based index, if it is set.
+fn column_width(xlsx: &[u8], col: u16) -> Option<f64> {
+    let sheet = xml(xlsx, "xl/worksheets/sheet1.xml");
+    let tag = &sheet[sheet.find(&format!("<col min=\"{col}\""))?..];
+    attribute(tag, "width")?.parse().ok()
+}
+
+#[test]
+fn autofit_widens_columns_to_their_content() {
+    let strings = StringArray::from(vec!["a rather long text value of forty chars"]);
+    let batches = vec![batch(vec![
+        ("s", Arc::new(strings)),
+        ("n", Arc::new(Int32Array::from(vec![1]))),
+    ])];
+    let (xlsx, _) = write(batches.clone(), &WriteOptions::default());
+    assert_eq!(column_width(&xlsx, 1), None);
+
+    let options = WriteOptions::builder().autofit(true).build();
+    let (xlsx, _) = write(batches, &options);
+    assert!(column_width(&xlsx, 1).unwrap() > 30.0);
+    assert!(column_width(&xlsx, 2).is_none_or(|width| width < 10.0));
+}
+
+#[test]
+fn ipc_file_is_told_from_a_stream_without_consuming_input() {
+    let numbers = batch(vec![("n", Arc::new(Int32Array::from(vec![1])))]);
+    let mut writer =
+        arrow::ipc::writer::FileWriter::try_new(Vec::new(), &numbers.schema()).unwrap();
+    writer.write(&numbers).unwrap();
+    let file = writer.into_inner().unwrap();
+    let stream = ipc_stream(&numbers.schema(), &[numbers]);
+
+    let mut reader = Cursor::new(&file);
+    assert!(is_ipc_file(&mut reader).unwrap());
+    assert_eq!(reader.position(), 0);
+    assert!(!is_ipc_file(&mut Cursor::new(&stream)).unwrap());
+    assert!(!is_ipc_file(&mut Cursor::new(b"")).unwrap());
+
+    // A reader that returns one byte per read, like a slow pipe.
+    struct Trickle<'a>(&'a [u8]);
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(self.0.len()).min(1);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+    let mut reader = buffer_magic(Trickle(&file)).unwrap();
+    assert!(is_ipc_file(&mut reader).unwrap());
+    let mut read = Vec::new();
+    reader.read_to_end(&mut read).unwrap();
+    assert_eq!(read, file);
+    assert!(!is_ipc_file(&mut buffer_magic(Trickle(&stream)).unwrap()).unwrap());
+    assert!(!is_ipc_file(&mut buffer_magic(Trickle(b"ARR")).unwrap()).unwrap());
+}
+
+#[test]
+fn constant_memory_writes_the_same_cells_with_inline_strings() {
+    let batches = vec![batch(vec![
+        ("n", Arc::new(Int32Array::from(vec![1, 2]))),
+        ("s", Arc::new(StringArray::from(vec![Some("x"), None]))),
+    ])];
+    let (buffered, _) = write(batches.clone(), &WriteOptions::default());
+    let options = WriteOptions::builder().constant_memory(true).build();
+    let (streamed, _) = write(batches, &options);
+    assert_eq!(cells(&streamed), cells(&buffered));
+    assert!(xml(&streamed, "xl/worksheets/sheet1.xml").contains(r#"t="inlineStr""#));
+    assert!(!xml(&buffered, "xl/worksheets/sheet1.xml").contains(r#"t="inlineStr""#));
+}
+
+#[test]
+fn workbook_is_saved_to_any_writer() {
+    let mut book = batch_to_workbook(
+        &batch(vec![("n", Arc::new(Int32Array::from(vec![7])))]),
+        "S",
+    )
+    .unwrap();
+    let mut xlsx = Vec::new();
+    save_to_writer(&mut book, &mut xlsx).unwrap();
+    assert_eq!(
+        cells(&xlsx),
+        [vec![Data::String("n".into())], vec![Data::Float(7.0)]]
+    );
+}
+
+#[test]
+fn summary_counts_the_rows_sheets_and_columns_written() {
+    let numbers = |values: Vec<i32>| batch(vec![("n", Arc::new(Int32Array::from(values)))]);
+    let (_, summary) = write(
+        vec![numbers(vec![1, 2, 3]), numbers(vec![4, 5])],
+        &WriteOptions::default(),
+    );
+    assert_eq!(summary.rows_written, 5);
+    assert_eq!(summary.sheets_written, 1);
+    assert_eq!(summary.columns, 1);
+    assert_eq!(summary.unsupported_cells, 0);
+}
+
+#[test]
+fn progress_is_reported_after_each_batch() {
+    let numbers = |values: Vec<i32>| Ok(batch(vec![("n", Arc::new(Int32Array::from(values)))]));
+    let mut reported = Vec::new();
+    let mut book = Workbook::new();
+    batch_iter2x_with_progress(
+        vec![numbers(vec![1, 2, 3]), numbers(vec![]), numbers(vec![4, 5])].into_iter(),
+        &mut book,
+        "S",
+        &WriteOptions::default(),
+        |rows| reported.push(rows),
+    )
+    .unwrap();
+    assert_eq!(reported, [3, 3, 5]);
+
+    let mut calls = 0;
+    batch_iter2x_with_progress(
+        std::iter::empty(),
+        &mut book,
+        "Empty",
+        &WriteOptions::default(),
+        |_| calls += 1,
+    )
+    .unwrap();
+    assert_eq!(calls, 0);
+}
+
+#[test]
+fn intervals_are_text_in_their_units() {
+    use arrow::datatypes::{IntervalDayTime, IntervalMonthDayNano};
+
+    let year_months = IntervalYearMonthArray::from(vec![14]);
+    let day_times = IntervalDayTimeArray::from(vec![IntervalDayTime::new(3, 3_723_500)]);
+    let month_day_nanos =
+        IntervalMonthDayNanoArray::from(vec![IntervalMonthDayNano::new(25, -2, 90_000_000_000)]);
+    assert_eq!(
+        write_cells(vec![batch(vec![
+            ("ym", Arc::new(year_months)),
+            ("dt", Arc::new(day_times)),
+            ("mdn", Arc::new(month_day_nanos)),
+        ])])[1],
+        [
+            Data::String("1y 2m".into()),
+            Data::String("3d 01:02:03.500".into()),
+            Data::String("2y 1m -2d 00:01:30".into()),
+        ]
+    );
+}
+
+#[test]
+fn durations_are_text_with_their_unit_or_seconds() {
+    let batches = vec![batch(vec![
+        ("s", Arc::new(DurationSecondArray::from(vec![90]))),
+        ("ms", Arc::new(DurationMillisecondArray::from(vec![1_500]))),
+        ("ns", Arc::new(DurationNanosecondArray::from(vec![-7]))),
+    ])];
+    assert_eq!(
+        write_cells(batches.clone())[1],
+        [
+            Data::String("90s".into()),
+            Data::String("1500ms".into()),
+            Data::String("-7ns".into()),
+        ]
+    );
+
+    let options = WriteOptions::builder().duration_as_seconds(true).build();
+    let (xlsx, _) = write(batches, &options);
+    assert_eq!(
+        cells(&xlsx)[1],
+        [Data::Float(90.0), Data::Float(1.5), Data::Float(-7e-9)]
+    );
+}
+
+#[test]
+fn binary_values_are_lowercase_hex() {
+    let binary = BinaryArray::from(vec![None, Some(&[0x00, 0xab, 0x10][..])]);
+    let fixed = FixedSizeBinaryArray::try_from_sparse_iter_with_size(
+        vec![None, Some([0xffu8, 0x01])].into_iter(),
+        2,
+    )
+    .unwrap();
+    assert_eq!(
+        write_cells(vec![batch(vec![
+            ("b", Arc::new(binary)),
+            ("f", Arc::new(fixed)),
+        ])]),
+        [
+            vec![Data::String("b".into()), Data::String("f".into())],
+            vec![Data::Empty, Data::Empty],
+            vec![Data::String("00ab10".into()), Data::String("ff01".into())],
+        ]
+    );
+}
+
+#[test]
+fn field_metadata_sets_the_number_format() {
+    let field = Field::new("n", DataType::Float64, false).with_metadata(HashMap::from([(
+        EXCEL_FORMAT_KEY.to_string(),
+        "#,##0.00".to_string(),
+    )]));
+    let numbers = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![field])),
+        vec![Arc::new(Float64Array::from(vec![1234.5]))],
+    )
+    .unwrap();
+    let (xlsx, _) = write(vec![numbers], &WriteOptions::default());
+    assert_eq!(cells(&xlsx)[1], [Data::Float(1234.5)]);
+    assert_eq!(num_format(&xlsx, "A2").as_deref(), Some("#,##0.00"));
+}
+
+#[test]
+fn sheet_names_are_made_acceptable_to_excel() {
+    assert_eq!(sanitize_sheet_name("a/b:c*d?[e]\\f"), "a_b_c_d__e__f");
+    assert_eq!(sanitize_sheet_name("'quoted'"), "_quoted_");
+    assert_eq!(sanitize_sheet_name(""), "Sheet1");
+    assert_eq!(
+        sanitize_sheet_name(&"é".repeat(40)),
+        "é".repeat(MAX_SHEET_NAME_CHARS)
+    );
+
+    let mut book = Workbook::new();
+    batch_iter2x(
+        vec![Ok(batch(vec![("n", Arc::new(Int32Array::from(vec![1])))]))].into_iter(),
+        &mut book,
+        "2024/01: sales?",
+    )
+    .unwrap();
+    assert_eq!(
+        sheet_names(&book.save_to_buffer().unwrap()),
+        ["2024_01_ sales_"]
+    );
+}
+
+#[test]
+fn non_finite_floats_are_text() {
+    let batches = vec![batch(vec![
+        (
+            "d",
+            Arc::new(Float64Array::from(vec![
+                f64::NAN,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                0.5,
+            ])),
+        ),
+        (
+            "f",
+            Arc::new(Float32Array::from(vec![f32::NAN, f32::INFINITY, 1.0, -2.0])),
+        ),
+    ])];
+    let text = |s: &str| Data::String(s.into());
+    assert_eq!(
+        write_cells(batches.clone())[1..],
+        [
+            vec![text("NaN"), text("NaN")],
+            vec![text("Inf"), text("Inf")],
+            vec![text("-Inf"), Data::Float(1.0)],
+            vec![Data::Float(0.5), Data::Float(-2.0)],
+        ]
+    );
+
+    let options = WriteOptions::builder()
+        .nan_text("n/a")
+        .infinity_text("+∞")
+        .neg_infinity_text("-∞")
+        .build();
+    let (xlsx, _) = write(batches, &options);
+    assert_eq!(
+        cells(&xlsx)[1..4],
+        [
+            vec![text("n/a"), text("n/a")],
+            vec![text("+∞"), text("+∞")],
+            vec![text("-∞"), Data::Float(1.0)],
+        ]
+    );
+}
+
+#[test]
+fn booleans_are_boolean_cells_or_text() {
+    let batches = vec![batch(vec![(
+        "b",
+        Arc::new(BooleanArray::from(vec![true, false])),
+    )])];
+    assert_eq!(
+        write_cells(batches.clone())[1..],
+        [vec![Data::Bool(true)], vec![Data::Bool(false)]]
+    );
+
+    let options = WriteOptions::builder().bool_as_text(true).build();
+    let (xlsx, _) = write(batches.clone(), &options);
+    assert_eq!(
+        cells(&xlsx)[1..],
+        [
+            vec![Data::String("TRUE".into())],
+            vec![Data::String("FALSE".into())]
+        ]
+    );
+
+    let options = WriteOptions::builder()
+        .bool_as_text(true)
+        .bool_labels("yes", "no")
+        .build();
+    let (xlsx, _) = write(batches, &options);
+    assert_eq!(
+        cells(&xlsx)[1..],
+        [
+            vec![Data::String("yes".into())],
+            vec![Data::String("no".into())]
+        ]
+    );
+}
+
+#[test]
+fn each_batch_gets_a_worksheet_of_its_own() {
+    let numbers = |values: Vec<i32>| batch(vec![("n", Arc::new(Int32Array::from(values)))]);
+    let options = WriteOptions::builder().sheet_per_batch(true).build();
+    let (xlsx, summary) = write(
+        vec![
+            numbers(vec![1, 2]),
+            numbers(vec![3]),
+            numbers(vec![4, 5, 6]),
+        ],
+        &options,
+    );
+    let header = || vec![Data::String("n".into())];
+    assert_eq!(
+        sheets(&xlsx),
+        [
+            (
+                "S_0".to_string(),
+                vec![header(), vec![Data::Float(1.0)], vec![Data::Float(2.0)]]
+            ),
+            ("S_1".to_string(), vec![header(), vec![Data::Float(3.0)]]),
+            (
+                "S_2".to_string(),
+                vec![
+                    header(),
+                    vec![Data::Float(4.0)],
+                    vec![Data::Float(5.0)],
+                    vec![Data::Float(6.0)]
+                ]
+            ),
+        ]
+    );
+    assert_eq!(summary.sheets_written, 3);
+    assert_eq!(summary.rows_written, 6);
+}
+
+#[test]
+fn rows_are_partitioned_into_worksheets_by_key() {
+    let batches = vec![
+        Ok(batch(vec![
+            (
+                "k",
+                Arc::new(StringArray::from(vec![Some("b"), Some("a"), None])),
+            ),
+            ("n", Arc::new(Int32Array::from(vec![1, 2, 3]))),
+        ])),
+        Ok(batch(vec![
+            ("k", Arc::new(StringArray::from(vec![Some("B")]))),
+            ("n", Arc::new(Int32Array::from(vec![4]))),
+        ])),
+    ];
+    let mut book = Workbook::new();
+    batch_iter2x_partitioned(batches.into_iter(), &mut book, "k").unwrap();
+    let header = || vec![Data::String("n".into())];
+    assert_eq!(
+        sheets(&book.save_to_buffer().unwrap()),
+        [
+            (
+                "b".to_string(),
+                vec![header(), vec![Data::Float(1.0)], vec![Data::Float(4.0)]]
+            ),
+            ("a".to_string(), vec![header(), vec![Data::Float(2.0)]]),
+            (
+                NULL_PARTITION_SHEET_NAME.to_string(),
+                vec![header(), vec![Data::Float(3.0)]]
+            ),
+        ]
+    );
+
+    let mut book = Workbook::new();
+    let result = batch_iter2x_partitioned(
+        vec![Ok(batch(vec![("n", Arc::new(Int32Array::from(vec![1])))]))].into_iter(),
+        &mut book,
+        "k",
+    );
+    let Err(Error::ColumnNotFound { name, available }) = result else {
+        panic!("unexpected result: {result:?}");
+    };
+    assert_eq!((name.as_str(), available), ("k", vec!["n".to_string()]));
+}
+
+#[test]
+fn start_offset_moves_the_header_and_data() {
+    let options = WriteOptions::builder().start_row(5).start_col(2).build();
+    let xlsx = write_numbers(&options);
+    let cells = cells(&xlsx);
+    assert_eq!(cells.len(), 9);
+    assert!(cells[..5].iter().flatten().all(|cell| *cell == Data::Empty));
+    let empty = || vec![Data::Empty, Data::Empty];
+    assert_eq!(cells[5], [empty(), vec![Data::String("n".into())]].concat());
+    assert_eq!(cells[8], [empty(), vec![Data::Float(3.0)]].concat());
+    let sheet = xml(&xlsx, "xl/worksheets/sheet1.xml");
+    assert!(sheet.contains(r#"<c r="C6""#));
+    assert!(sheet.contains(r#"topLeftCell="A7""#));
+
+    let out_of_range = [
+        WriteOptions::builder().start_row(MAX_ROWS - 1).build(),
+        WriteOptions::builder().start_col(MAX_COLS as u16).build(),
+    ];
+    for options in &out_of_range {
+        let mut book = Workbook::new();
+        let result = batch_iter2x_with_options(std::iter::empty(), &mut book, "S", options);
+        assert!(matches!(result, Err(Error::StartCellOutOfRange { .. })));
+    }
+
+    let mut book = Workbook::new();
+    let wide = (0..3).map(|i| {
+        (
+            format!("c{i}"),
+            Arc::new(Int32Array::from(vec![i])) as ArrayRef,
+        )
+    });
+    let result = batch_iter2x_with_options(
+        std::iter::once(Ok(RecordBatch::try_from_iter(wide).unwrap())),
+        &mut book,
+        "S",
+        &WriteOptions::builder()
+            .start_col(MAX_COLS as u16 - 2)
+            .build(),
+    );
+    assert!(matches!(
+        result,
+        Err(Error::TooManyColumns { found: 3, max: 2 })
+    ));
+}
+
+#[test]
+fn autofilter_spans_the_header_and_data() {
+    let sheet = xml(
+        &write_numbers(&WriteOptions::builder().autofilter(true).build()),
+        "xl/worksheets/sheet1.xml",
+    );
+    assert!(sheet.contains(r#"<autoFilter ref="A1:A4"/>"#));
+    let sheet = xml(
+        &write_numbers(&WriteOptions::default()),
+        "xl/worksheets/sheet1.xml",
+    );
+    assert!(!sheet.contains("<autoFilter"));
+}
+
+#[test]
+fn table_covers_the_header_and_data_with_its_style() {
+    let options = WriteOptions::builder()
+        .as_table(TableStyle::Medium9)
+        .autofilter(true)
+        .build();
+    let xlsx = write_numbers(&options);
+    let table = xml(&xlsx, "xl/tables/table1.xml");
+    assert!(table.contains(r#"ref="A1:A4""#));
+    assert!(table.contains(r#"<tableColumn id="1" name="n"/>"#));
+    assert!(table.contains(r#"name="TableStyleMedium9""#));
+    assert!(!xml(&xlsx, "xl/worksheets/sheet1.xml").contains("<autoFilter"));
+    assert_eq!(cells(&xlsx)[3], [Data::Float(3.0)]);
+
+    // The header row is flushed before the table is added, but keeps its cells.
+    let options = WriteOptions::builder()
+        .as_table(TableStyle::Medium9)
+        .constant_memory(true)
+        .build();
+    let xlsx = write_numbers(&options);
+    assert!(xml(&xlsx, "xl/tables/table1.xml").contains(r#"ref="A1:A4""#));
+    assert_eq!(cells(&xlsx)[0], [Data::String("n".into())]);
+}
+
+#[test]
+fn any_record_batch_reader_is_written() {
+    let numbers = batch(vec![("n", Arc::new(Int32Array::from(vec![1, 2])))]);
+    let schema = numbers.schema();
+    let mut book = Workbook::new();
+    reader2x(
+        RecordBatchIterator::new(vec![Ok(numbers)], schema.clone()),
+        &mut book,
+        "S",
+    )
+    .unwrap();
+    reader2x(
+        RecordBatchIterator::new(Vec::new(), schema),
+        &mut book,
+        "Empty",
+    )
+    .unwrap();
+    let header = || vec![Data::String("n".into())];
+    assert_eq!(
+        sheets(&book.save_to_buffer().unwrap()),
+        [
+            (
+                "S".to_string(),
+                vec![header(), vec![Data::Float(1.0)], vec![Data::Float(2.0)]]
+            ),
+            ("Empty".to_string(), vec![header()]),
+        ]
+    );
+}
+
+#[test]
+fn single_batch_becomes_a_workbook_with_one_worksheet() {
+    let mut book = batch_to_workbook(
+        &batch(vec![
+            ("n", Arc::new(Int32Array::from(vec![1]))),
+            ("s", Arc::new(StringArray::from(vec!["x"]))),
+        ]),
+        "Only",
+    )
+    .unwrap();
+    assert_eq!(
+        sheets(&book.save_to_buffer().unwrap()),
+        [(
+            "Only".to_string(),
+            vec![
+                vec![Data::String("n".into()), Data::String("s".into())],
+                vec![Data::Float(1.0), Data::String("x".into())],
+            ]
+        )]
+    );
+}
+
+#[test]
+fn maps_are_json_objects() {
+    let mut builder = MapBuilder::new(None, StringBuilder::new(), Int32Builder::new());
+    builder.keys().append_value("a");
+    builder.values().append_value(1);
+    builder.keys().append_value("b\"");
+    builder.values().append_null();
+    builder.append(true).unwrap();
+    builder.append(true).unwrap();
+    assert_eq!(
+        write_cells(vec![batch(vec![("m", Arc::new(builder.finish()))])])[1..],
+        [
+            vec![Data::String(r#"{"a":1,"b\"":null}"#.into())],
+            vec![Data::String("{}".into())],
+        ]
+    );
+}
+
+#[test]
+fn null_columns_are_empty_cells() {
+    let batches = vec![batch(vec![
+        ("z", Arc::new(NullArray::new(2))),
+        ("n", Arc::new(Int32Array::from(vec![1, 2]))),
+    ])];
+    let (xlsx, summary) = write(batches, &WriteOptions::default());
+    assert_eq!(
+        cells(&xlsx),
+        [
+            vec![Data::String("z".into()), Data::String("n".into())],
+            vec![Data::Empty, Data::Float(1.0)],
+            vec![Data::Empty, Data::Float(2.0)],
+        ]
+    );
+    assert_eq!(summary.unsupported_cells, 0);
+}
+
+#[test]
+fn dates_have_the_date_format_of_the_options() {
+    // 2024-02-29 is day 19,782 since the Unix epoch.
+    let batches = vec![batch(vec![
+        ("d32", Arc::new(Date32Array::from(vec![19_782]))),
+        (
+            "d64",
+            Arc::new(Date64Array::from(vec![19_782 * 86_400_000])),
+        ),
+    ])];
+    let (xlsx, _) = write(batches.clone(), &WriteOptions::default());
+    assert_eq!(serial(&cells(&xlsx)[1][0]), 45_351.0);
+    assert_eq!(serial(&cells(&xlsx)[1][1]), 45_351.0);
+    assert_eq!(num_format(&xlsx, "A2").as_deref(), Some("yyyy-mm-dd"));
+
+    let options = WriteOptions::builder().date_format("dd/mm/yyyy").build();
+    let (xlsx, _) = write(batches, &options);
+    assert_eq!(num_format(&xlsx, "A2").as_deref(), Some("dd/mm/yyyy"));
+    assert_eq!(num_format(&xlsx, "B2").as_deref(), Some("dd/mm/yyyy"));
+}
+
+#[test]
+fn fixed_size_lists_are_json_arrays() {
+    let lists = FixedSizeListArray::from_iter_primitive::<Int32Type, _, _>(
+        vec![
+            Some(vec![Some(1), None]),
+            None,
+            Some(vec![Some(3), Some(4)]),
+        ],
+        2,
+    );
+    assert_eq!(
+        write_cells(vec![batch(vec![("l", Arc::new(lists))])])[1..],
+        [
+            vec![Data::String("[1,null]".into())],
+            vec![Data::Empty],
+            vec![Data::String("[3,4]".into())],
+        ]
+    );
+
+    let floats = FixedSizeListArray::from_iter_primitive::<Float64Type, _, _>(
+        vec![
+            Some(vec![Some(1.0), Some(2.0), Some(3.0)]),
+            Some(vec![Some(0.5), None, Some(-2.25)]),
+        ],
+        3,
+    );
+    assert_eq!(
+        write_cells(vec![batch(vec![("f", Arc::new(floats))])])[1..],
+        [
+            vec![Data::String("[1.0,2.0,3.0]".into())],
+            vec![Data::String("[0.5,null,-2.25]".into())],
+        ]
+    );
+}
+
+#[test]
+fn transpose_writes_fields_as_rows() {
+    let batches = vec![batch(vec![
+        ("n", Arc::new(Int32Array::from(vec![1, 2]))),
+        ("s", Arc::new(StringArray::from(vec!["x", "y"]))),
+        ("b", Arc::new(BooleanArray::from(vec![true, false]))),
+    ])];
+    let options = WriteOptions::builder().transpose(true).build();
+    let (xlsx, summary) = write(batches, &options);
+    assert_eq!(
+        cells(&xlsx),
+        [
+            vec![Data::String("n".into()), Data::Float(1.0), Data::Float(2.0)],
+            vec![
+                Data::String("s".into()),
+                Data::String("x".into()),
+                Data::String("y".into())
+            ],
+            vec![
+                Data::String("b".into()),
+                Data::Bool(true),
+                Data::Bool(false)
+            ],
+        ]
+    );
+    assert_eq!(summary.rows_written, 2);
+}
+
+#[test]
+fn totals_row_sums_the_numeric_columns() {
+    let batches = vec![batch(vec![
+        ("n", Arc::new(Int32Array::from(vec![1, 2, 3]))),
+        ("s", Arc::new(StringArray::from(vec!["x", "y", "z"]))),
+    ])];
+    let options = WriteOptions::builder().totals_row(true).build();
+    let (xlsx, summary) = write(batches.clone(), &options);
+    let sheet = xml(&xlsx, "xl/worksheets/sheet1.xml");
+    assert!(sheet.contains("<c r=\"A5\"><f>SUM(A2:A4)</f>"));
+    assert!(!sheet.contains("<c r=\"B5\""));
+    assert_eq!(summary.rows_written, 3);
+
+    let options = WriteOptions::builder()
+        .totals_row(true)
+        .transpose(true)
+        .build();
+    let (xlsx, _) = write(batches, &options);
+    assert!(!xml(&xlsx, "xl/worksheets/sheet1.xml").contains("<f>"));
+}
+
+#[test]
+fn url_strings_are_hyperlinks_when_detected() {
+    let batches = vec![batch(vec![(
+        "u",
+        Arc::new(StringArray::from(vec!["https://example.com/a", "ftp://x"])),
+    )])];
+    let (xlsx, _) = write(batches.clone(), &WriteOptions::default());
+    assert!(!xml(&xlsx, "xl/worksheets/sheet1.xml").contains("<hyperlink "));
+
+    let options = WriteOptions::builder().detect_hyperlinks(true).build();
+    let (xlsx, _) = write(batches, &options);
+    let sheet = xml(&xlsx, "xl/worksheets/sheet1.xml");
+    assert!(sheet.contains(r#"<hyperlink ref="A2""#));
+    assert!(!sheet.contains(r#"<hyperlink ref="A3""#));
+    assert!(xml(&xlsx, "xl/worksheets/_rels/sheet1.xml.rels").contains("https://example.com/a"));
+    assert_eq!(
+        cells(&xlsx)[1..],
+        [
+            vec![Data::String("https://example.com/a".into())],
+            vec![Data::String("ftp://x".into())],
+        ]
+    );
+}
+
+#[test]
+fn unsupported_cells_are_placeholders_and_counted() {
+    let decimals = Decimal32Array::from(vec![Some(1), None, Some(3)])
+        .with_precision_and_scale(5, 2)
+        .unwrap();
+    let (xlsx, summary) = write(
+        vec![batch(vec![("d", Arc::new(decimals))])],
+        &WriteOptions::default(),
+    );
+    let placeholder = Data::String("unsupported data type: Decimal32(5, 2)".into());
+    assert_eq!(
+        cells(&xlsx)[1..],
+        [
+            vec![placeholder.clone()],
+            vec![Data::Empty],
+            vec![placeholder]
+        ]
+    );
+    assert_eq!(summary.unsupported_cells, 2);
+}
+
+#[test]
+fn strict_mode_fails_on_unsupported_types_even_if_null() {
+    let decimals = Decimal32Array::from(vec![None, None])
+        .with_precision_and_scale(5, 2)
+        .unwrap();
+    let options = WriteOptions::builder().strict(true).build();
+    let mut book = Workbook::new();
+    let result = batch_iter2x_with_options(
+        vec![Ok(batch(vec![("d", Arc::new(decimals))]))].into_iter(),
+        &mut book,
+        "S",
+        &options,
+    );
+    assert!(matches!(
+        result,
+        Err(Error::UnsupportedType(DataType::Decimal32(5, 2)))
+    ));
+
+    let (xlsx, _) = write(
+        vec![batch(vec![("n", Arc::new(Int32Array::from(vec![1])))])],
+        &options,
+    );
+    assert_eq!(cells(&xlsx)[1], [Data::Float(1.0)]);
+}
+
+#[test]
+fn unions_write_the_value_of_the_active_child() {
+    use arrow::buffer::ScalarBuffer;
+    use arrow::datatypes::UnionFields;
+
+    let fields = UnionFields::try_new(
+        [0, 1],
+        [
+            Field::new("i", DataType::Int32, true),
+            Field::new("s", DataType::Utf8, true),
+        ],
+    )
+    .unwrap();
+    let unions = UnionArray::try_new(
+        fields,
+        ScalarBuffer::from(vec![0i8, 1, 0]),
+        None,
+        vec![
+            Arc::new(Int32Array::from(vec![Some(5), None, None])),
+            Arc::new(StringArray::from(vec![None, Some("x"), None])),
+        ],
+    )
+    .unwrap();
+    let (xlsx, summary) = write(
+        vec![batch(vec![("u", Arc::new(unions))])],
+        &WriteOptions::default(),
+    );
+    assert_eq!(
+        cells(&xlsx)[1..],
+        [vec![Data::Float(5.0)], vec![Data::String("x".into())]]
+    );
+    assert_eq!(summary.unsupported_cells, 0);
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn batches_of_a_stream_are_written_in_order() {
+    use std::future::Future;
+    use std::task::{Context, Poll, Waker};
+
+    let numbers = |values: Vec<i32>| Ok(batch(vec![("n", Arc::new(Int32Array::from(values)))]));
+    let stream = futures::stream::iter(vec![numbers(vec![1]), numbers(vec![2, 3])]);
+    let mut book = Workbook::new();
+    let options = WriteOptions::default();
+    let summary = {
+        let mut written = std::pin::pin!(batch_stream2x(stream, &mut book, "S", &options));
+        // The stream is always ready, so the future completes on its first poll.
+        match written
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+        {
+            Poll::Ready(summary) => summary,
+            Poll::Pending => panic!("the write did not complete"),
+        }
+    };
+    assert_eq!(summary.unwrap().rows_written, 3);
+    assert_eq!(
+        cells(&book.save_to_buffer().unwrap())[1..],
+        [
+            vec![Data::Float(1.0)],
+            vec![Data::Float(2.0)],
+            vec![Data::Float(3.0)]
+        ]
+    );
+}
+
+#[test]
+fn field_metadata_sets_the_column_width() {
+    let field = Field::new("n", DataType::Int32, false).with_metadata(HashMap::from([(
+        EXCEL_WIDTH_KEY.to_string(),
+        "25.5".to_string(),
+    )]));
+    let numbers = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            field,
+            Field::new("m", DataType::Int32, false),
+        ])),
+        vec![
+            Arc::new(Int32Array::from(vec![1])),
+            Arc::new(Int32Array::from(vec![2])),
+        ],
+    )
+    .unwrap();
+    let (xlsx, _) = write(vec![numbers], &WriteOptions::default());
+    assert!((column_width(&xlsx, 1).unwrap() - 25.5).abs() < 1.0);
+    assert_eq!(column_width(&xlsx, 2), None);
+}
+
+#[test]
+fn notes_column_adds_notes_to_the_target_cells() {
+    let batches = vec![batch(vec![
+        ("n", Arc::new(Int32Array::from(vec![1, 2, 3]))),
+        (
+            "why",
+            Arc::new(StringArray::from(vec![Some("checked"), None, Some("")])),
+        ),
+    ])];
+    let options = WriteOptions::builder().notes_column("why", "n").build();
+    let (xlsx, _) = write(batches, &options);
+    assert_eq!(
+        cells(&xlsx),
+        [
+            vec![Data::String("n".into())],
+            vec![Data::Float(1.0)],
+            vec![Data::Float(2.0)],
+            vec![Data::Float(3.0)],
+        ]
+    );
+    let comments = xml(&xlsx, "xl/comments1.xml");
+    assert!(comments.contains(r#"<comment ref="A2""#));
+    assert!(comments.contains("checked"));
+    assert_eq!(comments.matches("<comment ref=").count(), 1);
+}
+
+#[test]
+fn every_supported_type_writes_its_cell() {
+    use arrow::datatypes::{Float16Type, IntervalDayTime, IntervalMonthDayNano};
+    use half::f16;
+
+    let mut map = MapBuilder::new(None, StringBuilder::new(), Int32Builder::new());
+    map.keys().append_value("k");
+    map.values().append_value(1);
+    map.append(true).unwrap();
+    let run_ends = Int32Array::from(vec![1]);
+    let fixed_list = FixedSizeListArray::from_iter_primitive::<Int32Type, _, _>(
+        vec![Some(vec![Some(1), Some(2)])],
+        2,
+    );
+    let columns: Vec<(&str, ArrayRef)> = vec![
+        ("null", Arc::new(NullArray::new(1))),
+        ("bool", Arc::new(BooleanArray::from(vec![true]))),
+        ("i8", Arc::new(Int8Array::from(vec![-8]))),
+        ("i16", Arc::new(Int16Array::from(vec![-16]))),
+        ("i32", Arc::new(Int32Array::from(vec![-32]))),
+        ("i64", Arc::new(Int64Array::from(vec![-64]))),
+        ("u8", Arc::new(UInt8Array::from(vec![8]))),
+        ("u16", Arc::new(UInt16Array::from(vec![16]))),
+        ("u32", Arc::new(UInt32Array::from(vec![32]))),
+        ("u64", Arc::new(UInt64Array::from(vec![64]))),
+        (
+            "f16",
+            Arc::new(PrimitiveArray::<Float16Type>::from(vec![f16::from_f32(
+                1.5,
+            )])),
+        ),
+        ("f32", Arc::new(Float32Array::from(vec![0.25]))),
+        ("f64", Arc::new(Float64Array::from(vec![0.125]))),
+        ("utf8", Arc::new(StringArray::from(vec!["s"]))),
+        ("large_utf8", Arc::new(LargeStringArray::from(vec!["l"]))),
+        ("utf8_view", Arc::new(StringViewArray::from(vec!["v"]))),
+        ("binary", Arc::new(BinaryArray::from(vec![&[1u8][..]]))),
+        (
+            "large_binary",
+            Arc::new(LargeBinaryArray::from(vec![&[2u8][..]])),
+        ),
+        (
+            "binary_view",
+            Arc::new(BinaryViewArray::from(vec![&[3u8][..]])),
+        ),
+        (
+            "fixed_binary",
+            Arc::new(FixedSizeBinaryArray::try_from_iter(vec![[4u8]].into_iter()).unwrap()),
+        ),
+        (
+            "decimal128",
+            Arc::new(
+                Decimal128Array::from(vec![125])
+                    .with_precision_and_scale(5, 2)
+                    .unwrap(),
+            ),
+        ),
+        (
+            "decimal256",
+            Arc::new(
+                Decimal256Array::from(vec![i256::from_i128(125)])
+                    .with_precision_and_scale(5, 2)
+                    .unwrap(),
+            ),
+        ),
+        ("date32", Arc::new(Date32Array::from(vec![0]))),
+        ("date64", Arc::new(Date64Array::from(vec![86_400_000]))),
+        (
+            "timestamp",
+            Arc::new(TimestampMicrosecondArray::from(vec![43_200_000_000])),
+        ),
+        (
+            "time32",
+            Arc::new(Time32MillisecondArray::from(vec![1_000])),
+        ),
+        (
+            "time64",
+            Arc::new(Time64MicrosecondArray::from(vec![1_000_000])),
+        ),
+        (
+            "interval_ym",
+            Arc::new(IntervalYearMonthArray::from(vec![13])),
+        ),
+        (
+            "interval_dt",
+            Arc::new(IntervalDayTimeArray::from(vec![IntervalDayTime::new(1, 0)])),
+        ),
+        (
+            "interval_mdn",
+            Arc::new(IntervalMonthDayNanoArray::from(vec![
+                IntervalMonthDayNano::new(1, 2, 0),
+            ])),
+        ),
+        (
+            "duration",
+            Arc::new(DurationMicrosecondArray::from(vec![5])),
+        ),
+        (
+            "list",
+            Arc::new(ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+                Some(vec![Some(1)]),
+            ])),
+        ),
+        (
+            "large_list",
+            Arc::new(LargeListArray::from_iter_primitive::<Int32Type, _, _>(
+                vec![Some(vec![Some(2)])],
+            )),
+        ),
+        ("fixed_list", Arc::new(fixed_list)),
+        ("map", Arc::new(map.finish())),
+        (
+            "dictionary",
+            Arc::new(vec!["d"].into_iter().collect::<DictionaryArray<Int8Type>>()),
+        ),
+        (
+            "run_end",
+            Arc::new(RunArray::try_new(&run_ends, &StringArray::from(vec!["r"])).unwrap()),
+        ),
+    ];
+    let (xlsx, summary) = write(vec![batch(columns)], &WriteOptions::default());
+    assert_eq!(summary.unsupported_cells, 0);
+    let cells = cells(&xlsx);
+    // Dates and times are compared by their serial number.
+    let row: Vec<(String, Data)> = cells[0]
+        .iter()
+        .map(|header| header.to_string())
+        .zip(cells[1].iter().map(|cell| match cell {
+            Data::DateTime(datetime) => Data::Float(datetime.as_f64()),
+            cell => cell.clone(),
+        }))
+        .collect();
+    let text = |s: &str| Data::String(s.into());
+    let expected = [
+        ("null", Data::Empty),
+        ("bool", Data::Bool(true)),
+        ("i8", Data::Float(-8.0)),
+        ("i16", Data::Float(-16.0)),
+        ("i32", Data::Float(-32.0)),
+        ("i64", Data::Float(-64.0)),
+        ("u8", Data::Float(8.0)),
+        ("u16", Data::Float(16.0)),
+        ("u32", Data::Float(32.0)),
+        ("u64", Data::Float(64.0)),
+        ("f16", Data::Float(1.5)),
+        ("f32", Data::Float(0.25)),
+        ("f64", Data::Float(0.125)),
+        ("utf8", text("s")),
+        ("large_utf8", text("l")),
+        ("utf8_view", text("v")),
+        ("binary", text("01")),
+        ("large_binary", text("02")),
+        ("binary_view", text("03")),
+        ("fixed_binary", text("04")),
+        ("decimal128", Data::Float(1.25)),
+        ("decimal256", text("1.25")),
+        ("date32", Data::Float(25_569.0)),
+        ("date64", Data::Float(25_570.0)),
+        ("timestamp", Data::Float(25_569.5)),
+        ("time32", Data::Float(1.0 / 86_400.0)),
+        ("time64", Data::Float(1.0 / 86_400.0)),
+        ("interval_ym", text("1y 1m")),
+        ("interval_dt", text("1d 00:00:00")),
+        ("interval_mdn", text("0y 1m 2d 00:00:00")),
+        ("duration", text("5us")),
+        ("list", text("[1]")),
+        ("large_list", text("[2]")),
+        ("fixed_list", text("[1,2]")),
+        ("map", text(r#"{"k":1}"#)),
+        ("dictionary", text("d")),
+        ("run_end", text("r")),
+    ];
+    assert_eq!(row.len(), expected.len());
+    for ((name, cell), (expected_name, expected_cell)) in row.iter().zip(expected) {
+        assert_eq!(name, expected_name);
+        assert_eq!(*cell, expected_cell, "{name}");
+    }
+}
+
+#[test]
+fn column_major_writing_matches_row_by_row_writing() {
+    // Constant memory mode writes row by row, so both modes must produce the same cells.
+    let batches: Vec<RecordBatch> = (0..3u32)
+        .map(|index| {
+            let rows = index * 4..index * 4 + 4;
+            batch(vec![
+                (
+                    "n",
+                    Arc::new(Int64Array::from_iter(
+                        rows.clone()
+                            .map(|row| (!row.is_multiple_of(3)).then_some(i64::from(row))),
+                    )),
+                ),
+                (
+                    "s",
+                    Arc::new(StringArray::from_iter(
+                        rows.clone()
+                            .map(|row| (!row.is_multiple_of(5)).then(|| format!("r{row}"))),
+                    )),
+                ),
+                (
+                    "d",
+                    Arc::new(Date32Array::from_iter_values(
+                        rows.clone().map(|row| row as i32),
+                    )),
+                ),
+                (
+                    "b",
+                    Arc::new(BooleanArray::from_iter(
+                        rows.map(|row| Some(row.is_multiple_of(2))),
+                    )),
+                ),
+            ])
+        })
+        .collect();
+    let (buffered, buffered_summary) = write(batches.clone(), &WriteOptions::default());
+    let options = WriteOptions::builder().constant_memory(true).build();
+    let (streamed, streamed_summary) = write(batches, &options);
+    assert_eq!(cells(&buffered).len(), 13);
+    assert_eq!(cells(&streamed), cells(&buffered));
+    assert_eq!(streamed_summary, buffered_summary);
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn parallel_worksheets_keep_the_order_of_the_readers() {
+    // The larger readers come first, so they tend to finish last.
+    let readers: Vec<(String, RecordBatchIterator<_>)> = (0..8u32)
+        .rev()
+        .map(|index| {
+            let numbers = Int32Array::from_iter_values(0..(index as i32 + 1) * 5_000);
+            let numbers = batch(vec![("n", Arc::new(numbers))]);
+            let schema = numbers.schema();
+            (
+                format!("Part {index}"),
+                RecordBatchIterator::new(vec![Ok(numbers)], schema),
+            )
+        })
+        .collect();
+    let mut book = Workbook::new();
+    let summaries = readers2x_parallel(readers, &mut book, &WriteOptions::default()).unwrap();
+    let rows: Vec<u64> = summaries
+        .iter()
+        .map(|summary| summary.rows_written)
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            40_000, 35_000, 30_000, 25_000, 20_000, 15_000, 10_000, 5_000
+        ]
+    );
+    let expected: Vec<String> = (0..8).rev().map(|index| format!("Part {index}")).collect();
+    assert_eq!(sheet_names(&book.save_to_buffer().unwrap()), expected);
+}
+
+#[test]
+fn binary_encoding_is_overridden_per_column() {
+    let image = Field::new("image", DataType::Binary, false).with_metadata(HashMap::from([(
+        BINARY_ENCODING_KEY.to_string(),
+        "base64".to_string(),
+    )]));
+    let hash = Field::new("hash", DataType::Binary, false);
+    let value = || Arc::new(BinaryArray::from(vec![&b"hi!"[..]])) as ArrayRef;
+    let batches = vec![
+        RecordBatch::try_new(
+            Arc::new(Schema::new(vec![image, hash])),
+            vec![value(), value()],
+        )
+        .unwrap(),
+    ];
+    assert_eq!(
+        write_cells(batches.clone())[1],
+        [Data::String("aGkh".into()), Data::String("686921".into())]
+    );
+
+    let options = WriteOptions::builder()
+        .binary_encoding(BinaryEncoding::Base64)
+        .build();
+    let (xlsx, _) = write(batches, &options);
+    assert_eq!(
+        cells(&xlsx)[1],
+        [Data::String("aGkh".into()), Data::String("aGkh".into())]
+    );
+}
+
+#[test]
+fn long_encoded_binary_is_truncated() {
+    let bytes = vec![0xabu8; 30_000];
+    let batches = vec![batch(vec![(
+        "b",
+        Arc::new(BinaryArray::from(vec![&bytes[..]])),
+    )])];
+    for encoding in [BinaryEncoding::Hex, BinaryEncoding::Base64] {
+        let options = WriteOptions::builder().binary_encoding(encoding).build();
+        let (xlsx, summary) = write(batches.clone(), &options);
+        let Data::String(text) = &cells(&xlsx)[1][0] else {
+            panic!("not a string cell");
+        };
+        assert_eq!(text.chars().count(), MAX_CELL_CHARS);
+        assert!(text.ends_with('…'));
+        assert_eq!(summary.truncated_cells, 1);
+    }
+}
+
+#[test]
+fn dates_before_1970_are_dates_and_out_of_range_ones_are_explained() {
+    let dates = Date32Array::from(vec![-10_000, -30_000, i32::MAX]);
+    let cells = write_cells(vec![batch(vec![("d", Arc::new(dates))])]);
+    // -10,000 days is 1942-08-16, 25,569 days after 1899-12-30 less 10,000.
+    assert_eq!(serial(&cells[1][0]), 15_569.0);
+    // Excel has no dates before 1900.
+    assert_eq!(cells[2][0], Data::String("1887-11-12".into()));
+    assert_eq!(
+        cells[3][0],
+        Data::String(format!("out of range date: {} days", i32::MAX))
+    );
+}
+
+#[test]
+fn concatenated_streams_are_read_as_one() {
+    let numbers = |values: Vec<i32>| batch(vec![("n", Arc::new(Int32Array::from(values)))]);
+    let schema = numbers(vec![]).schema();
+    let mut input = ipc_stream(&schema, &[numbers(vec![1, 2])]);
+    input.extend(ipc_stream(&schema, &[numbers(vec![3]), numbers(vec![4])]));
+    let reader = ConcatStreamReader::try_new(Cursor::new(input)).unwrap();
+    let mut book = Workbook::new();
+    let summary = reader2x_with_options(reader, &mut book, "S", &WriteOptions::default()).unwrap();
+    assert_eq!(summary.rows_written, 4);
+    assert_eq!(
+        cells(&book.save_to_buffer().unwrap())[1..],
+        [1.0, 2.0, 3.0, 4.0].map(|n| vec![Data::Float(n)])
+    );
+
+    let strings = batch(vec![("n", Arc::new(StringArray::from(vec!["x"])))]);
+    let mut input = ipc_stream(&schema, &[numbers(vec![1])]);
+    input.extend(ipc_stream(&strings.schema(), &[strings]));
+    let reader = ConcatStreamReader::try_new(Cursor::new(input)).unwrap();
+    let result = reader2x(reader, &mut Workbook::new(), "S");
+    assert!(matches!(result, Err(Error::SchemaMismatch { .. })));
+}
+
+#[test]
+fn integers_are_written_as_text_when_flagged() {
+    let zip = Field::new("zip", DataType::Int32, false).with_metadata(HashMap::from([(
+        NUMBER_AS_TEXT_KEY.to_string(),
+        "true".to_string(),
+    )]));
+    let count = Field::new("count", DataType::Int32, false);
+    let batches = vec![
+        RecordBatch::try_new(
+            Arc::new(Schema::new(vec![zip, count])),
+            vec![
+                Arc::new(Int32Array::from(vec![90_210])),
+                Arc::new(Int32Array::from(vec![7])),
+            ],
+        )
+        .unwrap(),
+    ];
+    let (xlsx, _) = write(batches.clone(), &WriteOptions::default());
+    assert_eq!(
+        cells(&xlsx)[1],
+        [Data::String("90210".into()), Data::Float(7.0)]
+    );
+
+    let options = WriteOptions::builder().numbers_as_text(true).build();
+    let (xlsx, _) = write(batches, &options);
+    assert_eq!(
+        cells(&xlsx)[1],
+        [Data::String("90210".into()), Data::String("7".into())]
+    );
+}
+
+#[test]
+fn worksheets_are_protected_with_an_optional_password() {
+    let sheet = |options: &WriteOptions| xml(&write_numbers(options), "xl/worksheets/sheet1.xml");
+    assert!(!sheet(&WriteOptions::default()).contains("<sheetProtection"));
+
+    let unlocked = sheet(&WriteOptions::builder().protect(None).build());
+    let protection = &unlocked[unlocked.find("<sheetProtection").unwrap()..];
+    assert_eq!(attribute(protection, "sheet"), Some("1"));
+    assert_eq!(attribute(protection, "password"), None);
+
+    let locked = sheet(
+        &WriteOptions::builder()
+            .protect(Some("secret".into()))
+            .build(),
+    );
+    let protection = &locked[locked.find("<sheetProtection").unwrap()..];
+    assert!(attribute(protection, "password").is_some());
+    assert!(!locked.contains("secret"));
+}
+
+#[test]
+fn decimal_grouping_adds_a_thousands_separator() {
+    let decimals = || {
+        Decimal128Array::from(vec![123_456_789])
+            .with_precision_and_scale(12, 2)
+            .unwrap()
+    };
+    let options = WriteOptions::builder().decimal_grouping(true).build();
+    let (xlsx, _) = write(vec![batch(vec![("d", Arc::new(decimals()))])], &options);
+    assert_eq!(cells(&xlsx)[1], [Data::Float(1_234_567.89)]);
+    assert_eq!(num_format(&xlsx, "A2").as_deref(), Some("#,##0.00"));
+
+    let (xlsx, _) = write(
+        vec![batch(vec![("d", Arc::new(decimals()))])],
+        &WriteOptions::default(),
+    );
+    assert_eq!(num_format(&xlsx, "A2").as_deref(), Some("0.00"));
+}
+
+#[test]
+fn numeric_columns_get_a_color_scale() {
+    let batches = vec![batch(vec![
+        ("n", Arc::new(Int32Array::from(vec![1, 2, 3]))),
+        ("s", Arc::new(StringArray::from(vec!["x", "y", "z"]))),
+    ])];
+    let options = WriteOptions::builder().color_scale_numeric(true).build();
+    let (xlsx, _) = write(batches.clone(), &options);
+    let sheet = xml(&xlsx, "xl/worksheets/sheet1.xml");
+    assert!(sheet.contains(r#"<conditionalFormatting sqref="A2:A4">"#));
+    assert!(sheet.contains(r#"<cfRule type="colorScale""#));
+    assert_eq!(sheet.matches("<conditionalFormatting ").count(), 1);
+
+    let options = WriteOptions::builder()
+        .color_scale_numeric(true)
+        .transpose(true)
+        .build();
+    let (xlsx, _) = write(batches, &options);
+    assert!(!xml(&xlsx, "xl/worksheets/sheet1.xml").contains("<conditionalFormatting"));
+}
+
+#[test]
+fn sheet_names_come_from_the_namer() {
+    let numbers = |values: Vec<i32>| batch(vec![("n", Arc::new(Int32Array::from(values)))]);
+    let options = WriteOptions::builder()
+        .sheet_per_batch(true)
+        .sheet_names(|index| format!("Part-{index}/x"))
+        .build();
+    let (xlsx, _) = write(vec![numbers(vec![1]), numbers(vec![2])], &options);
+    assert_eq!(sheet_names(&xlsx), ["Part-0_x", "Part-1_x"]);
+}
+
+#[test]
+fn row_numbers_count_across_batches() {
+    let numbers = |values: Vec<i32>| batch(vec![("n", Arc::new(Int32Array::from(values)))]);
+    let options = WriteOptions::builder().row_numbers(true).build();
+    let (xlsx, _) = write(vec![numbers(vec![10, 20]), numbers(vec![30])], &options);
+    assert_eq!(
+        cells(&xlsx),
+        [
+            vec![
+                Data::String(ROW_NUMBER_HEADER.into()),
+                Data::String("n".into())
+            ],
+            vec![Data::Float(1.0), Data::Float(10.0)],
+            vec![Data::Float(2.0), Data::Float(20.0)],
+            vec![Data::Float(3.0), Data::Float(30.0)],
+        ]
+    );
+    let sheet = xml(&xlsx, "xl/worksheets/sheet1.xml");
+    assert!(sheet.contains(r#"<pane xSplit="1" ySplit="1" topLeftCell="B2""#));
+}
+
+#[test]
+fn batch_range_selects_whole_batches() {
+    let numbers = |value: i32| Ok(batch(vec![("n", Arc::new(Int32Array::from(vec![value])))]));
+    let failing = Err(arrow::error::ArrowError::IpcError("not read".into()));
+    let options = WriteOptions::builder().batch_range(1..=1).build();
+    let mut book = Workbook::new();
+    // The batches after the range are not read.
+    batch_iter2x_with_options(
+        vec![numbers(1), numbers(2), failing].into_iter(),
+        &mut book,
+        "S",
+        &options,
+    )
+    .unwrap();
+    assert_eq!(
+        cells(&book.save_to_buffer().unwrap()),
+        [vec![Data::String("n".into())], vec![Data::Float(2.0)]]
+    );
+}
+
+#[test]
+fn schema_sheet_lists_the_fields() {
+    let field = Field::new("n", DataType::Int32, false)
+        .with_metadata(HashMap::from([("unit".to_string(), "kg".to_string())]));
+    let batches = vec![
+        RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                field,
+                Field::new("s", DataType::Utf8, true),
+            ])),
+            vec![
+                Arc::new(Int32Array::from(vec![1])),
+                Arc::new(StringArray::from(vec!["x"])),
+            ],
+        )
+        .unwrap(),
+    ];
+    let options = WriteOptions::builder().emit_schema_sheet(true).build();
+    let (xlsx, summary) = write(batches, &options);
+    assert_eq!(summary.sheets_written, 2);
+    let text = |s: &str| Data::String(s.into());
+    assert_eq!(
+        sheets(&xlsx)[1],
+        (
+            SCHEMA_SHEET_NAME.to_string(),
+            vec![
+                vec![
+                    text("Name"),
+                    text("Type"),
+                    text("Nullable"),
+                    text("Metadata")
+                ],
+                vec![
+                    text("n"),
+                    text("Int32"),
+                    Data::Bool(false),
+                    text(r#"{"unit":"kg"}"#)
+                ],
+                vec![text("s"), text("Utf8"), Data::Bool(true), Data::Empty],
+            ]
+        )
+    );
+
+    let mut book = Workbook::new();
+    batch_iter2x_with_options(
+        std::iter::once(Ok(batch(vec![("n", Arc::new(Int32Array::from(vec![1])))]))),
+        &mut book,
+        "schema",
+        &options,
+    )
+    .unwrap();
+    assert_eq!(
+        sheet_names(&book.save_to_buffer().unwrap()),
+        ["schema", "Schema (2)"]
+    );
+}
+
+#[test]
+fn null_text_marks_nulls_but_not_empty_strings() {
+    let batches = vec![batch(vec![
+        (
+            "s",
+            Arc::new(StringArray::from(vec![Some(""), None, Some("x")])),
+        ),
+        ("n", Arc::new(Int32Array::from(vec![None, Some(1), None]))),
+    ])];
+    let options = WriteOptions::builder().null_text("NULL").build();
+    let (xlsx, _) = write(batches, &options);
+    let null = || Data::String("NULL".into());
+    assert_eq!(
+        cells(&xlsx)[1..],
+        [
+            vec![Data::Empty, null()],
+            vec![null(), Data::Float(1.0)],
+            vec![Data::String("x".into()), null()],
+        ]
+    );
+}
+
+#[test]
+fn header_overrides_replace_the_column_names() {
+    let batches = vec![batch(vec![
+        ("col_0", Arc::new(Int32Array::from(vec![1]))),
+        ("col_1", Arc::new(Int32Array::from(vec![2]))),
+    ])];
+    let options = WriteOptions::builder()
+        .header_overrides(vec!["Id".into(), "Amount".into()])
+        .build();
+    let (xlsx, _) = write(batches.clone(), &options);
+    assert_eq!(
+        cells(&xlsx)[0],
+        [Data::String("Id".into()), Data::String("Amount".into())]
+    );
+
+    let options = WriteOptions::builder()
+        .header_overrides(vec!["Id".into()])
+        .build();
+    let result = batch_iter2x_with_options(
+        batches.into_iter().map(Ok),
+        &mut Workbook::new(),
+        "S",
+        &options,
+    );
+    assert!(matches!(
+        result,
+        Err(Error::HeaderCountMismatch {
+            expected: 2,
+            found: 1
+        })
+    ));
+}
+
+#[test]
+fn float16_nan_is_text_and_normal_values_are_exact() {
+    use arrow::datatypes::Float16Type;
+    use half::f16;
+
+    let halves = PrimitiveArray::<Float16Type>::from(vec![
+        f16::NAN,
+        f16::from_f32(0.1),
+        f16::NEG_INFINITY,
+        f16::MAX,
+    ]);
+    let cells = write_cells(vec![batch(vec![("h", Arc::new(halves))])]);
+    assert_eq!(
+        cells[1..],
+        [
+            vec![Data::String("NaN".into())],
+            // The nearest half to 0.1, converted exactly rather than rounded.
+            vec![Data::Float(0.0999755859375)],
+            vec![Data::String("-Inf".into())],
+            vec![Data::Float(65_504.0)],
+        ]
+    );
+}
+
+#[test]
+fn cell_writer_intercepts_the_cells_it_handles() {
+    let batches = vec![batch(vec![
+        ("s", Arc::new(StringArray::from(vec![Some("abc"), None]))),
+        ("n", Arc::new(Int32Array::from(vec![1, 2]))),
+    ])];
+    let options = WriteOptions::builder()
+        .cell_writer(|worksheet, row, col, array, index| {
+            let Some(strings) = array.as_string_opt::<i32>() else {
+                return Ok(false);
+            };
+            if strings.is_valid(index) {
+                worksheet.write_string(row, col, strings.value(index).to_uppercase())?;
+            }
+            Ok(true)
+        })
+        .build();
+    let (xlsx, _) = write(batches, &options);
+    assert_eq!(
+        cells(&xlsx)[1..],
+        [
+            vec![Data::String("ABC".into()), Data::Float(1.0)],
+            vec![Data::Empty, Data::Float(2.0)],
+        ]
+    );
+}
+
+#[test]
+fn json_lines_round_trip_the_values() {
+    use serde_json::{Value, json};
+
+    let batches = vec![batch(vec![
+        ("n", Arc::new(Int32Array::from(vec![Some(1), None]))),
+        ("f", Arc::new(Float64Array::from(vec![0.5, f64::NAN]))),
+        ("s", Arc::new(StringArray::from(vec!["a \"q\"\n", "é"]))),
+        ("d", Arc::new(Date32Array::from(vec![19_782, 0]))),
+        ("b", Arc::new(BooleanArray::from(vec![true, false]))),
+        (
+            "l",
+            Arc::new(ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+                Some(vec![Some(1), None]),
+                None,
+            ])),
+        ),
+    ])];
+    let mut out = Vec::new();
+    let rows = batch_iter2json(batches.into_iter().map(Ok), &mut out).unwrap();
+    assert_eq!(rows, 2);
+    let lines: Vec<Value> = String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            json!({"n": 1, "f": 0.5, "s": "a \"q\"\n", "d": "2024-02-29", "b": true, "l": [1, null]}),
+            json!({"n": null, "f": null, "s": "é", "d": "1970-01-01", "b": false, "l": null}),
+        ]
+    );
+}
+
+#[test]
+fn csv_output_quotes_fields_that_need_it() {
+    let batches = vec![batch(vec![
+        (
+            "s",
+            Arc::new(StringArray::from(vec![
+                Some("a,b"),
+                None,
+                Some("say \"hi\""),
+            ])),
+        ),
+        ("n", Arc::new(Int32Array::from(vec![1, 2, 3]))),
+        ("d", Arc::new(Date32Array::from(vec![0, 1, 2]))),
+    ])];
+    let mut out = Vec::new();
+    let rows = batch_iter2csv(batches.into_iter().map(Ok), &mut out, b',').unwrap();
+    assert_eq!(rows, 3);
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "s,n,d\n\"a,b\",1,1970-01-01\n,2,1970-01-02\n\"say \"\"hi\"\"\",3,1970-01-03\n"
+    );
+}
+
+#[test]
+fn title_is_merged_above_the_shifted_header() {
+    let batches = vec![batch(vec![
+        ("a", Arc::new(Int32Array::from(vec![1]))),
+        ("b", Arc::new(Int32Array::from(vec![2]))),
+    ])];
+    let options = WriteOptions::builder().title("Report").build();
+    let (xlsx, _) = write(batches, &options);
+    assert_eq!(
+        cells(&xlsx),
+        [
+            vec![Data::String("Report".into()), Data::Empty],
+            vec![Data::String("a".into()), Data::String("b".into())],
+            vec![Data::Float(1.0), Data::Float(2.0)],
+        ]
+    );
+    let sheet = xml(&xlsx, "xl/worksheets/sheet1.xml");
+    assert!(sheet.contains(r#"<mergeCell ref="A1:B1"/>"#));
+    assert!(sheet.contains(r#"<pane ySplit="2" topLeftCell="A3""#));
+    assert!(font(&xlsx, "A1").contains("<b/>"));
+}
+
+#[test]
+fn unexpected_time_units_and_values_are_not_left_blank() {
+    let batches = vec![batch(vec![
+        ("t32", Arc::new(Time32SecondArray::from(vec![90_000]))),
+        ("t64", Arc::new(Time64MicrosecondArray::from(vec![-1]))),
+        ("ts", Arc::new(TimestampSecondArray::from(vec![i64::MAX]))),
+    ])];
+    assert_eq!(
+        write_cells(batches)[1],
+        [
+            Data::String("out of range time: 90000 s".into()),
+            Data::String("out of range time: -1 us".into()),
+            Data::String(format!("out of range timestamp: {} s", i64::MAX)),
+        ]
+    );
+
+    // Arrow has no arrays of such a unit, but a schema may still declare one.
+    let schema = Schema::new(vec![Field::new(
+        "t",
+        DataType::Time32(TimeUnit::Microsecond),
+        true,
+    )]);
+    let options = WriteOptions::builder().strict(true).build();
+    let result = batch_iter2x_with_schema(
+        std::iter::empty(),
+        &schema,
+        &mut Workbook::new(),
+        "S",
+        &options,
+    );
+    assert!(matches!(
+        result,
+        Err(Error::UnsupportedType(DataType::Time32(
+            TimeUnit::Microsecond
+        )))
+    ));
+}
+
+#[test]
+fn dates_use_the_1900_date_system() {
+    // 1900-03-01 and 1899-12-31 are 25,508 and 25,568 days before the Unix epoch.
+    let dates = Date32Array::from(vec![-25_508, -25_568]);
+    let (xlsx, _) = write(
+        vec![batch(vec![("d", Arc::new(dates))])],
+        &WriteOptions::default(),
+    );
+    assert!(!xml(&xlsx, "xl/workbook.xml").contains("date1904"));
+    let cells = cells(&xlsx);
+    // Serial 61, past the day of the 1900 leap year bug that Excel keeps.
+    assert_eq!(serial(&cells[1][0]), 61.0);
+    assert_eq!(cells[2][0], Data::String("1899-12-31".into()));
+}
+
+#[test]
+fn defined_name_covers_the_data() {
+    let options = WriteOptions::builder().define_name("Numbers").build();
+    let workbook = xml(&write_numbers(&options), "xl/workbook.xml");
+    assert!(workbook.contains(r#"<definedName name="Numbers">S!$A$1:$A$4</definedName>"#));
+
+    let options = WriteOptions::builder()
+        .define_name("Records")
+        .transpose(true)
+        .build();
+    let batches = vec![batch(vec![
+        ("n", Arc::new(Int32Array::from(vec![1, 2]))),
+        ("s", Arc::new(StringArray::from(vec!["x", "y"]))),
+    ])];
+    let workbook = xml(&write(batches, &options).0, "xl/workbook.xml");
+    assert!(workbook.contains(r#"<definedName name="Records">S!$A$1:$C$2</definedName>"#));
+}
+
+#[test]
+fn run_end_encoded_values_are_expanded() {
+    let run_ends = Int32Array::from(vec![2, 3, 5]);
+    let values = Int32Array::from(vec![Some(7), None, Some(9)]);
+    let runs = RunArray::try_new(&run_ends, &values).unwrap();
+    assert_eq!(
+        write_cells(vec![batch(vec![("r", Arc::new(runs))])])[1..],
+        [
+            vec![Data::Float(7.0)],
+            vec![Data::Float(7.0)],
+            vec![Data::Empty],
+            vec![Data::Float(9.0)],
+            vec![Data::Float(9.0)],
+        ]
+    );
+}
+
+#[test]
+fn naive_timestamps_are_shifted_to_the_chosen_timezone() {
+    // 2024-01-15 12:00:00 UTC is 21:00 in Tokyo.
+    let batches = vec![batch(vec![(
+        "t",
+        Arc::new(TimestampSecondArray::from(vec![1_705_320_000])),
+    )])];
+    let (xlsx, _) = write(batches.clone(), &WriteOptions::default());
+    assert!((serial(&cells(&xlsx)[1][0]) - (45_306.0 + 12.0 / 24.0)).abs() < 1e-9);
+
+    let options = WriteOptions::builder()
+        .naive_timestamp_tz("Asia/Tokyo".parse().unwrap())
+        .build();
+    let (xlsx, _) = write(batches, &options);
+    assert!((serial(&cells(&xlsx)[1][0]) - (45_306.0 + 21.0 / 24.0)).abs() < 1e-9);
+}
+
+#[test]
+fn rows_roll_over_to_a_new_worksheet_at_the_last_excel_row() {
+    // The header is on the third to last row, leaving room for two data rows per worksheet.
+    let options = WriteOptions::builder()
+        .start_row(MAX_ROWS - 3)
+        .freeze_header(false)
+        .build();
+    let numbers = Int32Array::from(vec![1, 2, 3, 4, 5]);
+    let (xlsx, summary) = write(vec![batch(vec![("n", Arc::new(numbers))])], &options);
+    assert_eq!(summary.rows_written, 5);
+    assert_eq!(summary.sheets_written, 3);
+
+    let mut book: Xlsx<_> = Xlsx::new(Cursor::new(&xlsx)).unwrap();
+    assert_eq!(book.sheet_names(), ["S", "S (2)", "S (3)"]);
+    let last_row = MAX_ROWS - 1;
+    let expected = [
+        ("S", vec![Data::Float(1.0), Data::Float(2.0)]),
+        ("S (2)", vec![Data::Float(3.0), Data::Float(4.0)]),
+        ("S (3)", vec![Data::Float(5.0)]),
+    ];
+    for (name, values) in expected {
+        let range = book.worksheet_range(name).unwrap();
+        assert_eq!(
+            range.get_value((last_row - 2, 0)),
+            Some(&Data::String("n".into()))
+        );
+        for (row, value) in (last_row - 1..).zip(&values) {
+            assert_eq!(range.get_value((row, 0)), Some(value), "{name}");
+        }
+        assert_eq!(range.end().unwrap().0, last_row - 2 + values.len() as u32);
+    }
+}
+
+#[test]
+fn formula_columns_write_formulas_and_keep_other_text() {
+    let batches = vec![batch(vec![
+        ("n", Arc::new(Int32Array::from(vec![2, 3]))),
+        ("f", Arc::new(StringArray::from(vec!["=A2*10", "plain"]))),
+    ])];
+    let options = WriteOptions::builder()
+        .formula_columns(vec!["f".into()])
+        .build();
+    let (xlsx, _) = write(batches, &options);
+    let sheet = xml(&xlsx, "xl/worksheets/sheet1.xml");
+    assert!(sheet.contains("<c r=\"B2\"><f>A2*10</f>"));
+    assert_eq!(
+        cells(&xlsx)[2],
+        [Data::Float(3.0), Data::String("plain".into())]
+    );
+
+    let options = WriteOptions::builder()
+        .formula_columns(vec!["missing".into()])
+        .build();
+    let result = batch_iter2x_with_options(
+        vec![Ok(batch(vec![("n", Arc::new(Int32Array::from(vec![1])))]))].into_iter(),
+        &mut Workbook::new(),
+        "S",
+        &options,
+    );
+    assert!(matches!(result, Err(Error::ColumnNotFound { .. })));
+}
+
+#[test]
+fn runs_of_equal_values_are_merged_across_batches() {
+    let keys = |values: Vec<&str>| batch(vec![("k", Arc::new(StringArray::from(values)))]);
+    let options = WriteOptions::builder().merge_runs(vec!["k".into()]).build();
+    let (xlsx, _) = write(vec![keys(vec!["a", "a"]), keys(vec!["a", "b"])], &options);
+    let sheet = xml(&xlsx, "xl/worksheets/sheet1.xml");
+    assert!(sheet.contains(r#"<mergeCells count="1"><mergeCell ref="A2:A4"/>"#));
+    assert_eq!(
+        cells(&xlsx)[1..],
+        [
+            vec![Data::String("a".into())],
+            vec![Data::Empty],
+            vec![Data::Empty],
+            vec![Data::String("b".into())],
+        ]
+    );
+
+    let options = WriteOptions::builder()
+        .merge_runs(vec!["k".into()])
+        .as_table(TableStyle::Medium9)
+        .build();
+    let (xlsx, _) = write(vec![keys(vec!["a", "a"]), keys(vec!["a", "b"])], &options);
+    assert!(!xml(&xlsx, "xl/worksheets/sheet1.xml").contains("<mergeCell"));
+    assert!(xml(&xlsx, "xl/tables/table1.xml").contains(r#"ref="A1:A5""#));
+    assert_eq!(cells(&xlsx)[3], [Data::String("a".into())]);
+}
+
+#[test]
+fn column_stats_count_nulls_and_track_the_range() {
+    let numbers = |values: Vec<Option<f64>>| {
+        batch(vec![
+            ("f", Arc::new(Float64Array::from(values))),
+            ("s", Arc::new(StringArray::from(vec![None, Some("x")]))),
+        ])
+    };
+    let (_, summary) = write(
+        vec![
+            numbers(vec![Some(2.5), None]),
+            numbers(vec![None, Some(-1.0)]),
+        ],
+        &WriteOptions::default(),
+    );
+    assert_eq!(
+        summary.column_stats,
+        [
+            ColumnStats {
+                nulls: 2,
+                min: Some(-1.0),
+                max: Some(2.5)
+            },
+            ColumnStats {
+                nulls: 2,
+                min: None,
+                max: None
+            },
+        ]
+    );
+}
+
+#[test]
+fn long_strings_are_truncated_with_an_ellipsis_and_counted() {
+    let long = "x".repeat(40_000);
+    let list = ListArray::from_iter_primitive::<Int32Type, _, _>(vec![Some(vec![Some(1); 20_000])]);
+    let batches = vec![batch(vec![
+        ("s", Arc::new(StringArray::from(vec![long.as_str()]))),
+        ("l", Arc::new(LargeStringArray::from(vec![long.as_str()]))),
+        ("j", Arc::new(list)),
+    ])];
+    let (xlsx, summary) = write(batches, &WriteOptions::default());
+    assert_eq!(summary.truncated_cells, 3);
+    for cell in &cells(&xlsx)[1] {
+        let Data::String(text) = cell else {
+            panic!("not a string cell: {cell:?}");
+        };
+        assert_eq!(text.chars().count(), MAX_CELL_CHARS);
+        assert!(text.ends_with('…'));
+    }
+
+    let options = WriteOptions::builder().max_cell_chars(4).build();
+    let (xlsx, summary) = write(
+        vec![batch(vec![(
+            "s",
+            Arc::new(StringArray::from(vec!["abcdef", "abcd"])),
+        )])],
+        &options,
+    );
+    assert_eq!(summary.truncated_cells, 1);
+    assert_eq!(
+        cells(&xlsx)[1..],
+        [
+            vec![Data::String("abc…".into())],
+            vec![Data::String("abcd".into())]
+        ]
+    );
+}
+
+#[test]
+fn merged_runs_count_each_truncated_cell_once() {
+    let long = "y".repeat(40_000);
+    let keys = StringArray::from(vec![long.as_str(), long.as_str(), "short"]);
+    let options = WriteOptions::builder().merge_runs(vec!["k".into()]).build();
+    let (xlsx, summary) = write(vec![batch(vec![("k", Arc::new(keys))])], &options);
+    assert_eq!(summary.truncated_cells, 2);
+    assert!(xml(&xlsx, "xl/worksheets/sheet1.xml").contains(r#"<mergeCell ref="A2:A3"/>"#));
+}
+
+#[test]
+fn filtered_rows_are_the_only_ones_written_and_counted() {
+    let batches = vec![
+        batch(vec![
+            (
+                "keep",
+                Arc::new(BooleanArray::from(vec![true, false, true])),
+            ),
+            ("n", Arc::new(Int64Array::from(vec![1, 2, 3]))),
+        ]),
+        batch(vec![
+            (
+                "keep",
+                Arc::new(BooleanArray::from(vec![false, true, false])),
+            ),
+            ("n", Arc::new(Int64Array::from(vec![4, 5, 6]))),
+        ]),
+    ];
+    let keep = |batch: &RecordBatch, row: usize| {
+        batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<BooleanArray>()
+            .is_some_and(|flags| flags.value(row))
+    };
+    let mut book = Workbook::new();
+    let summary = batch_iter2x_filtered_with_options(
+        batches.into_iter().map(Ok),
+        &mut book,
+        "S",
+        keep,
+        &WriteOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(summary.rows_written, 3);
+    let xlsx = book.save_to_buffer().unwrap();
+    let numbers: Vec<_> = cells(&xlsx)[1..].iter().map(|row| row[1].clone()).collect();
+    assert_eq!(
+        numbers,
+        [Data::Float(1.0), Data::Float(3.0), Data::Float(5.0)]
+    );
+}
+
+#[test]
+fn format_cache_creates_each_format_once() {
+    let mut formats = FormatCache::default();
+    let date = formats.get(FormatKey::Number("yyyy-mm-dd".into()));
+    assert!(Arc::ptr_eq(
+        &date,
+        &formats.get(FormatKey::Number("yyyy-mm-dd".into()))
+    ));
+    assert!(Arc::ptr_eq(
+        &formats.get(FormatKey::Title),
+        &formats.get(FormatKey::Title)
+    ));
+    assert_eq!(formats.formats.len(), 2);
+}
+
+#[test]
+fn wide_sheets_with_repeated_formats_share_their_styles() {
+    let decimals = Decimal128Array::from(vec![12_345])
+        .with_precision_and_scale(10, 2)
+        .unwrap();
+    let templates: [ArrayRef; 4] = [
+        Arc::new(Date32Array::from(vec![19_000])),
+        Arc::new(decimals),
+        Arc::new(TimestampMillisecondArray::from(vec![1_700_000_000_000])),
+        Arc::new(Time32SecondArray::from(vec![3_600])),
+    ];
+    let names: Vec<String> = (0..4_000).map(|i| format!("c{i}")).collect();
+    let columns = names
+        .iter()
+        .zip(templates.iter().cycle())
+        .map(|(name, array)| (name.as_str(), Arc::clone(array)))
+        .collect();
+    let options = WriteOptions::builder().title("Wide").build();
+    let (xlsx, summary) = write(vec![batch(columns)], &options);
+    assert_eq!(summary.columns, 4_000);
+
+    let styles = xml(&xlsx, "xl/styles.xml");
+    let cell_xfs = &styles[styles.find("<cellXfs").unwrap()..];
+    let count: usize = attribute(cell_xfs, "count").unwrap().parse().unwrap();
+    assert!(count < 10, "{count} cell styles");
+    assert_eq!(num_format(&xlsx, "A3"), num_format(&xlsx, "E3"));
+    assert_eq!(num_format(&xlsx, "B3").as_deref(), Some("0.00"));
+    assert_eq!(num_format(&xlsx, "B3"), num_format(&xlsx, "EWT3"));
+}
+
+/// Returns a struct column `s` of the child `c` and an integer child `n`, null at the second row.
+fn nullable_struct(child: ArrayRef) -> RecordBatch {
+    let fields = Fields::from(vec![
+        Field::new("c", child.data_type().clone(), true),
+        Field::new("n", DataType::Int32, false),
+    ]);
+    let numbers = Arc::new(Int32Array::from(vec![1, 2, 3])) as ArrayRef;
+    let nulls = NullBuffer::from(vec![true, false, true]);
+    let structs = StructArray::new(fields, vec![child, numbers], Some(nulls));
+    batch(vec![("s", Arc::new(structs))])
+}
+
+/// Checks that the null second row of [`nullable_struct`] is null in every output.
+fn assert_struct_nulls(batches: Vec<RecordBatch>, first: Data, last: Data) {
+    let options = WriteOptions::builder().null_text("-").build();
+    let (xlsx, summary) = write(batches.clone(), &options);
+    let null = || Data::String("-".into());
+    assert_eq!(
+        cells(&xlsx)[1..],
+        [
+            vec![first, Data::Float(1.0)],
+            vec![null(), null()],
+            vec![last, Data::Float(3.0)],
+        ]
+    );
+    assert_eq!(summary.column_stats[0].nulls, 1);
+
+    let mut json = Vec::new();
+    batch_iter2json(batches.clone().into_iter().map(Ok), &mut json).unwrap();
+    let json = String::from_utf8(json).unwrap();
+    assert_eq!(json.lines().nth(1), Some(r#"{"s.c":null,"s.n":null}"#));
+
+    let mut csv = Vec::new();
+    batch_iter2csv(batches.into_iter().map(Ok), &mut csv, b',').unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    assert_eq!(csv.lines().nth(2), Some(","));
+}
+
+#[test]
+fn run_end_encoded_children_of_null_structs_are_null() {
+    let run_ends = Int32Array::from(vec![2, 3]);
+    let runs = RunArray::try_new(&run_ends, &StringArray::from(vec!["a", "b"])).unwrap();
+    assert_struct_nulls(
+        vec![nullable_struct(Arc::new(runs))],
+        Data::String("a".into()),
+        Data::String("b".into()),
+    );
+}
+
+#[test]
+fn union_children_of_null_structs_are_null() {
+    use arrow::buffer::ScalarBuffer;
+    use arrow::datatypes::UnionFields;
+
+    let fields = UnionFields::try_new(
+        [0, 1],
+        [
+            Field::new("i", DataType::Int32, true),
+            Field::new("s", DataType::Utf8, true),
+        ],
+    )
+    .unwrap();
+    let unions = UnionArray::try_new(
+        fields,
+        ScalarBuffer::from(vec![0i8, 0, 1]),
+        None,
+        vec![
+            Arc::new(Int32Array::from(vec![Some(5), Some(6), None])),
+            Arc::new(StringArray::from(vec![None, None, Some("x")])),
+        ],
+    )
+    .unwrap();
+    assert_struct_nulls(
+        vec![nullable_struct(Arc::new(unions))],
+        Data::Float(5.0),
+        Data::String("x".into()),
+    );
+}
@@ -0,0 +1,672 @@
+//! Tests of the `arrow-ipc-stream2x` command.
+
+#![allow(clippy::unwrap_used)]
+
+use std::io::Write;
+use std::net::TcpListener;
+use std::process::{Command, Output, Stdio};
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, Int32Array, StringArray};
+use arrow::ipc::writer::{FileWriter, StreamWriter};
+use arrow::record_batch::RecordBatch;
+use calamine::{Data, Reader, Xlsx, open_workbook};
+use tempfile::TempDir;
+
+/// A batch of an integer column `a` and a string column `b`.
+fn sample_batch() -> RecordBatch {
+    RecordBatch::try_from_iter([
+        ("a", Arc::new(Int32Array::from(vec![1, 2])) as ArrayRef),
+        ("b", Arc::new(StringArray::from(vec!["x", "y"])) as ArrayRef),
+    ])
+    .unwrap()
+}
+
+/// The cells of a worksheet written from [`sample_batch`].
+fn sample_cells() -> Vec<Vec<Data>> {
+    vec![
+        vec![Data::String("a".into()), Data::String("b".into())],
+        vec![Data::Float(1.0), Data::String("x".into())],
+        vec![Data::Float(2.0), Data::String("y".into())],
+    ]
+}
+
+/// Encodes the batches as an Arrow IPC stream.
+fn ipc_stream(batches: &[RecordBatch]) -> Vec<u8> {
+    let mut writer = StreamWriter::try_new(Vec::new(), &batches[0].schema()).unwrap();
+    for batch in batches {
+        writer.write(batch).unwrap();
+    }
+    writer.into_inner().unwrap()
+}
+
+/// Encodes the batches as an Arrow IPC file.
+fn ipc_file(batches: &[RecordBatch]) -> Vec<u8> {
+    let mut writer = FileWriter::try_new(Vec::new(), &batches[0].schema()).unwrap();
+    for batch in batches {
+        writer.write(batch).unwrap();
+    }
+    writer.into_inner().unwrap()
+}
+
+/// Runs the command with the arguments and fails unless it succeeds.
+fn run(args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_arrow-ipc-stream2x"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+/// Runs the command with the arguments and the input on stdin, and fails unless it succeeds.
+fn run_with_stdin(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_arrow-ipc-stream2x"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+/// Returns the names and cells of the worksheets of a workbook file, from `A1` on.
+fn sheets(path: &str) -> Vec<(String, Vec<Vec<Data>>)> {
+    let mut book: Xlsx<_> = open_workbook(path).unwrap();
+    book.sheet_names()
+        .into_iter()
+        .map(|name| {
+            let range = book.worksheet_range(&name).unwrap();
+            let (last_row, last_col) = range.end().unwrap_or_default();
+            let rows = (0..=last_row)
+                .map(|row| {
+                    (0..=last_col)
+                        .map(|col| range.get_value((row, col)).cloned().unwrap_or_default())
+                        .collect()
+                })
+                .collect();
+            (name, rows)
+        })
+        .collect()
+}
+
+/// Returns a part of a workbook file, such as `xl/worksheets/sheet1.xml`.
+fn xml(path: &str, part: &str) -> String {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(path).unwrap()).unwrap();
+    std::io::read_to_string(archive.by_name(part).unwrap()).unwrap()
+}
+
+/// Returns the path of a file in the directory as a string.
+fn path(dir: &TempDir, name: &str) -> String {
+    dir.path().join(name).to_str().unwrap().to_string()
+}
+
+#[test]
+fn reads_the_ipc_file_and_stream_formats() {
+    let dir = TempDir::new().unwrap();
+    let (stream, file) = (path(&dir, "d.arrows"), path(&dir, "d.arrow"));
+    std::fs::write(&stream, ipc_stream(&[sample_batch()])).unwrap();
+    std::fs::write(&file, ipc_file(&[sample_batch()])).unwrap();
+    let (from_stream, from_file) = (path(&dir, "s.xlsx"), path(&dir, "f.xlsx"));
+    run(&["-i", &stream, "--format", "stream", "-o", &from_stream]);
+    run(&["-i", &file, "--format", "file", "-o", &from_file]);
+    assert_eq!(sheets(&from_stream)[0].1, sample_cells());
+    assert_eq!(sheets(&from_file)[0].1, sample_cells());
+}
+
+#[test]
+fn detects_the_ipc_format_of_files_and_stdin() {
+    let dir = TempDir::new().unwrap();
+    let file = path(&dir, "d.arrow");
+    std::fs::write(&file, ipc_file(&[sample_batch()])).unwrap();
+    let from_file = path(&dir, "f.xlsx");
+    run(&["-i", &file, "-o", &from_file]);
+    assert_eq!(sheets(&from_file)[0].1, sample_cells());
+
+    let from_stdin = path(&dir, "s.xlsx");
+    run_with_stdin(&["-o", &from_stdin], &ipc_stream(&[sample_batch()]));
+    assert_eq!(sheets(&from_stdin)[0].1, sample_cells());
+}
+
+#[test]
+fn writes_the_selected_columns_in_order() {
+    let dir = TempDir::new().unwrap();
+    let output = path(&dir, "o.xlsx");
+    run_with_stdin(
+        &["--columns", "b,a", "-o", &output],
+        &ipc_stream(&[sample_batch()]),
+    );
+    let swapped: Vec<Vec<Data>> = sample_cells()
+        .into_iter()
+        .map(|row| row.into_iter().rev().collect())
+        .collect();
+    assert_eq!(sheets(&output)[0].1, swapped);
+
+    let wide = RecordBatch::try_from_iter(["a", "b", "c", "d"].map(|name| {
+        let column = Arc::new(StringArray::from(vec![name.to_uppercase()])) as ArrayRef;
+        (name, column)
+    }))
+    .unwrap();
+    run_with_stdin(
+        &["--columns", "d,b", "-o", &output],
+        &ipc_stream(std::slice::from_ref(&wide)),
+    );
+    let text = |text: &str| Data::String(text.into());
+    assert_eq!(
+        sheets(&output)[0].1,
+        [vec![text("d"), text("b")], vec![text("D"), text("B")]]
+    );
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_arrow-ipc-stream2x"))
+        .args(["--columns", "a,e", "-o", &output])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(&ipc_stream(&[wide]))
+        .unwrap();
+    let failed = child.wait_with_output().unwrap();
+    assert!(!failed.status.success());
+    let stderr = String::from_utf8(failed.stderr).unwrap();
+    assert!(
+        stderr.contains(r#"ColumnNotFound { name: "e", available: ["a", "b", "c", "d"] }"#),
+        "{stderr}"
+    );
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn reads_parquet_files() {
+    use parquet::arrow::ArrowWriter;
+
+    let dir = TempDir::new().unwrap();
+    let input = path(&dir, "d.parquet");
+    let batch = sample_batch();
+    let mut writer =
+        ArrowWriter::try_new(std::fs::File::create(&input).unwrap(), batch.schema(), None).unwrap();
+    writer.write(&batch).unwrap();
+    writer.close().unwrap();
+    let output = path(&dir, "o.xlsx");
+    run(&["-i", &input, "--format", "parquet", "-o", &output]);
+    assert_eq!(sheets(&output)[0].1, sample_cells());
+}
+
+#[test]
+fn reads_csv_with_inferred_types() {
+    let dir = TempDir::new().unwrap();
+    let input = path(&dir, "d.csv");
+    std::fs::write(&input, "a;b\n1;x\n2;y\n").unwrap();
+    let output = path(&dir, "o.xlsx");
+    run(&[
+        "-i",
+        &input,
+        "--format",
+        "csv",
+        "--csv-delimiter",
+        ";",
+        "-o",
+        &output,
+    ]);
+    assert_eq!(sheets(&output)[0].1, sample_cells());
+}
+
+/// Runs the command, writing the input to its stdin in two writes split at `at` with a pause
+/// between them, so the first read of the command sees only the first part.
+fn run_with_split_stdin(args: &[&str], input: &[u8], at: usize) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_arrow-ipc-stream2x"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(&input[..at]).unwrap();
+    stdin.flush().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    stdin.write_all(&input[at..]).unwrap();
+    drop(stdin);
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn detects_a_file_piped_in_small_writes() {
+    let dir = TempDir::new().unwrap();
+    let output = path(&dir, "o.xlsx");
+    let result = run_with_split_stdin(&["-o", &output], &ipc_file(&[sample_batch()]), 3);
+    assert!(
+        result.status.success(),
+        "{}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+    assert_eq!(sheets(&output)[0].1, sample_cells());
+}
+
+#[test]
+fn decompresses_gzip_input() {
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&ipc_stream(&[sample_batch()])).unwrap();
+    let gzip = encoder.finish().unwrap();
+    let dir = TempDir::new().unwrap();
+    let input = path(&dir, "d.arrows.gz");
+    std::fs::write(&input, &gzip).unwrap();
+    let (from_file, from_stdin) = (path(&dir, "f.xlsx"), path(&dir, "s.xlsx"));
+    run(&["-i", &input, "-o", &from_file]);
+    run_with_stdin(&["--gzip", "-o", &from_stdin], &gzip);
+    assert_eq!(sheets(&from_file)[0].1, sample_cells());
+    assert_eq!(sheets(&from_stdin)[0].1, sample_cells());
+}
+
+#[test]
+fn writes_each_input_to_its_own_worksheet() {
+    let dir = TempDir::new().unwrap();
+    let (first, second) = (path(&dir, "sales.arrows"), path(&dir, "costs.arrow"));
+    std::fs::write(&first, ipc_stream(&[sample_batch()])).unwrap();
+    std::fs::write(&second, ipc_file(&[sample_batch()])).unwrap();
+    let output = path(&dir, "o.xlsx");
+    run(&["-i", &first, "-i", &second, "-o", &output]);
+    assert_eq!(
+        sheets(&output),
+        [
+            ("sales".to_string(), sample_cells()),
+            ("costs".to_string(), sample_cells()),
+        ]
+    );
+
+    run(&["-i", &first, "-i", &second, "-s", "First", "-o", &output]);
+    let names: Vec<String> = sheets(&output).into_iter().map(|(name, _)| name).collect();
+    assert_eq!(names, ["First", "costs"]);
+}
+
+#[test]
+fn numbers_the_worksheets_of_inputs_with_the_same_name() {
+    let dir = TempDir::new().unwrap();
+    let long_name = "x".repeat(31);
+    let inputs: Vec<String> = ["a/data", "b/data", "c/DATA", "a/long", "b/long"]
+        .iter()
+        .map(|name| {
+            let name = name.replace("long", &long_name);
+            std::fs::create_dir_all(dir.path().join(&name[..1])).unwrap();
+            let input = path(&dir, &format!("{name}.arrows"));
+            std::fs::write(&input, ipc_stream(&[sample_batch()])).unwrap();
+            input
+        })
+        .collect();
+    let output = path(&dir, "o.xlsx");
+    let mut args = Vec::new();
+    for input in &inputs {
+        args.extend(["-i", input.as_str()]);
+    }
+    args.extend(["-o", output.as_str()]);
+    run(&args);
+    let names: Vec<String> = sheets(&output).into_iter().map(|(name, _)| name).collect();
+    assert_eq!(
+        names,
+        [
+            "data".to_string(),
+            "data (2)".to_string(),
+            "DATA (3)".to_string(),
+            long_name.clone(),
+            format!("{} (2)", &long_name[..27]),
+        ]
+    );
+}
+
+/// Encodes the numbers `0..rows` as a stream of an integer column `n`, in batches of `batch_len`.
+fn numbers_stream(rows: i32, batch_len: usize) -> Vec<u8> {
+    let numbers: Vec<i32> = (0..rows).collect();
+    let batches: Vec<RecordBatch> = numbers
+        .chunks(batch_len)
+        .map(|chunk| {
+            let column = Arc::new(Int32Array::from(chunk.to_vec())) as ArrayRef;
+            RecordBatch::try_from_iter([("n", column)]).unwrap()
+        })
+        .collect();
+    ipc_stream(&batches)
+}
+
+/// The cells of a worksheet of a column `n` holding the numbers.
+fn numbers_cells(numbers: std::ops::Range<i32>) -> Vec<Vec<Data>> {
+    std::iter::once(vec![Data::String("n".into())])
+        .chain(numbers.map(|n| vec![Data::Float(f64::from(n))]))
+        .collect()
+}
+
+#[test]
+fn writes_at_most_max_rows() {
+    let dir = TempDir::new().unwrap();
+    let output = path(&dir, "o.xlsx");
+    run_with_stdin(&["--max-rows", "5", "-o", &output], &numbers_stream(100, 3));
+    assert_eq!(sheets(&output)[0].1, numbers_cells(0..5));
+}
+
+#[test]
+fn skips_rows_across_batches() {
+    let dir = TempDir::new().unwrap();
+    let output = path(&dir, "o.xlsx");
+    run_with_stdin(&["--skip-rows", "3", "-o", &output], &numbers_stream(10, 2));
+    assert_eq!(sheets(&output)[0].1, numbers_cells(3..10));
+
+    run_with_stdin(
+        &["--skip-rows", "3", "--max-rows", "5", "-o", &output],
+        &numbers_stream(100, 4),
+    );
+    assert_eq!(sheets(&output)[0].1, numbers_cells(3..8));
+}
+
+#[test]
+fn names_the_worksheet_from_schema_metadata() {
+    let batch = sample_batch();
+    let schema = batch
+        .schema()
+        .as_ref()
+        .clone()
+        .with_metadata([("table".to_string(), "sales/2024".to_string())].into());
+    let named = RecordBatch::try_new(Arc::new(schema), batch.columns().to_vec()).unwrap();
+    let dir = TempDir::new().unwrap();
+    let output = path(&dir, "o.xlsx");
+    run_with_stdin(
+        &["--sheet-from-metadata", "table", "-o", &output],
+        &ipc_stream(std::slice::from_ref(&named)),
+    );
+    assert_eq!(
+        sheets(&output),
+        [("sales_2024".to_string(), sample_cells())]
+    );
+
+    run_with_stdin(
+        &[
+            "--sheet-from-metadata",
+            "missing",
+            "-s",
+            "Fallback",
+            "-o",
+            &output,
+        ],
+        &ipc_stream(&[batch]),
+    );
+    assert_eq!(sheets(&output), [("Fallback".to_string(), sample_cells())]);
+
+    let (first, second) = (path(&dir, "first.arrows"), path(&dir, "second.arrows"));
+    let named = ipc_stream(&[named]);
+    std::fs::write(&first, &named).unwrap();
+    std::fs::write(&second, &named).unwrap();
+    run(&[
+        "--sheet-from-metadata",
+        "table",
+        "-i",
+        &first,
+        "-i",
+        &second,
+        "-o",
+        &output,
+    ]);
+    assert_eq!(
+        sheets(&output),
+        [
+            ("sales_2024".to_string(), sample_cells()),
+            ("sales_2024 (2)".to_string(), sample_cells()),
+        ]
+    );
+}
+
+#[test]
+fn freezes_the_leading_columns_with_the_header() {
+    let dir = TempDir::new().unwrap();
+    let output = path(&dir, "o.xlsx");
+    run_with_stdin(
+        &["--freeze-columns", "1", "-o", &output],
+        &ipc_stream(&[sample_batch()]),
+    );
+    let sheet = xml(&output, "xl/worksheets/sheet1.xml");
+    assert!(sheet.contains(r#"<pane xSplit="1" ySplit="1" topLeftCell="B2""#));
+    assert_eq!(sheets(&output)[0].1, sample_cells());
+}
+
+#[test]
+fn sets_the_document_properties() {
+    let dir = TempDir::new().unwrap();
+    let output = path(&dir, "o.xlsx");
+    run_with_stdin(
+        &[
+            "--title",
+            "Sales",
+            "--author",
+            "Ann",
+            "--company",
+            "ACME",
+            "-o",
+            &output,
+        ],
+        &ipc_stream(&[sample_batch()]),
+    );
+    let core = xml(&output, "docProps/core.xml");
+    assert!(core.contains("<dc:title>Sales</dc:title>"));
+    assert!(core.contains("<dc:creator>Ann</dc:creator>"));
+    assert!(xml(&output, "docProps/app.xml").contains("<Company>ACME</Company>"));
+
+    run_with_stdin(
+        &["--title", "", "-o", &output],
+        &ipc_stream(&[sample_batch()]),
+    );
+    assert!(!xml(&output, "docProps/core.xml").contains("<dc:title>"));
+}
+
+/// Writes an Arrow IPC stream file above the size that turns on constant memory mode, and
+/// returns its path.
+fn large_input(dir: &TempDir) -> String {
+    // Three strings of 23 MB make a file above the 64 MiB threshold.
+    let large = "x".repeat(23_000_000);
+    let strings = RecordBatch::try_from_iter([(
+        "s",
+        Arc::new(StringArray::from(vec![large.as_str(); 3])) as ArrayRef,
+    )])
+    .unwrap();
+    let input = path(dir, "large.arrows");
+    std::fs::write(&input, ipc_stream(&[strings])).unwrap();
+    input
+}
+
+#[test]
+fn writes_large_input_files_in_constant_memory_mode() {
+    let dir = TempDir::new().unwrap();
+    let (large_input, small_input) = (large_input(&dir), path(&dir, "small.arrows"));
+    std::fs::write(&small_input, ipc_stream(&[sample_batch()])).unwrap();
+    let (large_output, small_output) = (path(&dir, "large.xlsx"), path(&dir, "small.xlsx"));
+    run(&["-i", &large_input, "-o", &large_output]);
+    run(&["-i", &small_input, "-o", &small_output]);
+    // Constant memory worksheets hold their strings inline rather than in a shared table.
+    assert!(xml(&large_output, "xl/worksheets/sheet1.xml").contains(r#"t="inlineStr""#));
+    assert!(!xml(&small_output, "xl/worksheets/sheet1.xml").contains(r#"t="inlineStr""#));
+}
+
+#[test]
+fn writes_large_gzip_and_ipc_file_inputs_in_constant_memory_mode() {
+    use arrow::array::{DictionaryArray, NullArray};
+    use arrow::datatypes::Int8Type;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    let is_constant_memory =
+        |output: &str| xml(output, "xl/worksheets/sheet1.xml").contains(r#"t="inlineStr""#);
+    let dir = TempDir::new().unwrap();
+    let output = path(&dir, "o.xlsx");
+
+    // Nine MB of strings, stored without compression, is above the gzip threshold but below the
+    // one of uncompressed input.
+    let strings = "x".repeat(3_000_000);
+    let strings = RecordBatch::try_from_iter([(
+        "s",
+        Arc::new(StringArray::from(vec![strings.as_str(); 3])) as ArrayRef,
+    )])
+    .unwrap();
+    let (plain, gzip) = (path(&dir, "plain.arrows"), path(&dir, "gzip.arrows.gz"));
+    std::fs::write(&plain, ipc_stream(std::slice::from_ref(&strings))).unwrap();
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::none());
+    encoder.write_all(&ipc_stream(&[strings])).unwrap();
+    std::fs::write(&gzip, encoder.finish().unwrap()).unwrap();
+    run(&["-i", &plain, "-o", &output]);
+    assert!(!is_constant_memory(&output));
+    run(&["-i", &gzip, "-o", &output]);
+    assert!(is_constant_memory(&output));
+
+    // Ten million cells in a small file, as a dictionary column and null columns take little
+    // space.
+    let rows = 500_000;
+    let keys = vec![0i8; rows];
+    let dictionary =
+        DictionaryArray::<Int8Type>::try_new(keys.into(), Arc::new(StringArray::from(vec!["x"])))
+            .unwrap();
+    let mut columns = vec![("d".to_string(), Arc::new(dictionary) as ArrayRef)];
+    for index in 1..20 {
+        columns.push((
+            format!("n{index}"),
+            Arc::new(NullArray::new(rows)) as ArrayRef,
+        ));
+    }
+    let cells = RecordBatch::try_from_iter(columns).unwrap();
+    let file = path(&dir, "cells.arrow");
+    std::fs::write(&file, ipc_file(&[cells])).unwrap();
+    assert!(std::fs::metadata(&file).unwrap().len() < 1_000_000);
+    run(&["-i", &file, "-o", &output]);
+    assert!(is_constant_memory(&output));
+}
+
+#[test]
+fn warns_of_an_unknown_timezone() {
+    use arrow::array::TimestampSecondArray;
+
+    let timestamps = TimestampSecondArray::from(vec![0]).with_timezone("Mars/Olympus");
+    let batch = RecordBatch::try_from_iter([("t", Arc::new(timestamps) as ArrayRef)]).unwrap();
+    let dir = TempDir::new().unwrap();
+    let output = path(&dir, "o.xlsx");
+    let stderr = run_with_stdin(&["-o", &output], &ipc_stream(&[batch])).stderr;
+    let stderr = String::from_utf8(stderr).unwrap();
+    assert!(
+        stderr.starts_with(r#"warn: column "t": "#) && stderr.contains("Mars/Olympus"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn reports_the_rows_and_worksheets_by_verbosity() {
+    let dir = TempDir::new().unwrap();
+    let output = path(&dir, "o.xlsx");
+    let input = ipc_stream(&[sample_batch(), sample_batch()]);
+    let stderr = |args: &[&str]| {
+        let args = [args, &["-o", &output]].concat();
+        String::from_utf8(run_with_stdin(&args, &input).stderr).unwrap()
+    };
+    let summary = "info: stdin: wrote 4 rows to 1 worksheets\n";
+
+    let verbose = stderr(&["-v"]);
+    assert!(verbose.contains(summary), "{verbose}");
+    assert!(verbose.contains(&format!("info: saved the workbook to {output}\n")));
+    assert!(!verbose.contains("debug: "));
+    assert!(stderr(&["-vv"]).contains("debug: "));
+    assert_eq!(stderr(&[]), "");
+    assert_eq!(stderr(&["-q", "-s", "bad/name"]), "");
+    assert!(stderr(&["-s", "bad/name"]).starts_with("warn: "));
+}
+
+#[test]
+fn reads_a_stream_from_a_tcp_server() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let (mut connection, _) = listener.accept().unwrap();
+        connection
+            .write_all(&ipc_stream(&[sample_batch()]))
+            .unwrap();
+    });
+    let dir = TempDir::new().unwrap();
+    let output = path(&dir, "o.xlsx");
+    run(&["-i", &format!("tcp://{address}"), "-o", &output]);
+    server.join().unwrap();
+    let sheet_name = address.to_string().replace(':', "_");
+    assert_eq!(sheets(&output), [(sheet_name, sample_cells())]);
+
+    // Nothing listens on the port any more.
+    let failed = Command::new(env!("CARGO_BIN_EXE_arrow-ipc-stream2x"))
+        .args(["-i", &format!("tcp://{address}"), "-o", &output])
+        .output()
+        .unwrap();
+    assert!(!failed.status.success());
+    let stderr = String::from_utf8(failed.stderr).unwrap();
+    assert!(
+        stderr.contains(&format!("cannot connect to {address}")),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn warns_that_merge_runs_is_ignored_for_large_inputs() {
+    let dir = TempDir::new().unwrap();
+    let input = large_input(&dir);
+    let output = path(&dir, "o.xlsx");
+    let stderr = run(&["-i", &input, "--merge-runs", "s", "-o", &output]).stderr;
+    let stderr = String::from_utf8(stderr).unwrap();
+    assert!(
+        stderr.contains(&format!("warn: {input}: --merge-runs is ignored")),
+        "{stderr}"
+    );
+
+    let stderr = run(&[
+        "-i",
+        &input,
+        "--constant-memory",
+        "--merge-runs",
+        "s",
+        "-o",
+        &output,
+    ])
+    .stderr;
+    assert!(!String::from_utf8(stderr).unwrap().contains("--merge-runs"));
+}
+
+#[test]
+fn rejects_feather_v1_input_with_a_hint() {
+    let dir = TempDir::new().unwrap();
+    let input = path(&dir, "legacy.feather");
+    std::fs::write(&input, b"FEA1\0\0\0\0FEA1").unwrap();
+    let output = path(&dir, "o.xlsx");
+    for format in ["auto", "file", "stream"] {
+        let failed = Command::new(env!("CARGO_BIN_EXE_arrow-ipc-stream2x"))
+            .args(["-i", &input, "--format", format, "-o", &output])
+            .output()
+            .unwrap();
+        assert!(!failed.status.success());
+        let stderr = String::from_utf8(failed.stderr).unwrap();
+        assert!(
+            stderr.contains("Feather v1 input is not supported"),
+            "{stderr}"
+        );
+    }
+    assert!(!std::path::Path::new(&output).exists());
+
+    let failed = run_with_split_stdin(&["-o", &output], b"FEA1\0\0\0\0FEA1", 2);
+    assert!(!failed.status.success());
+    let stderr = String::from_utf8(failed.stderr).unwrap();
+    assert!(
+        stderr.contains("Feather v1 input is not supported"),
+        "{stderr}"
+    );
+}
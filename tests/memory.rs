@@ -0,0 +1,84 @@
+//! Tests of the memory use of constant memory mode.
+//!
+//! The peak memory of the whole process is measured, so these tests have a binary of their own.
+
+#![cfg(target_os = "linux")]
+#![allow(clippy::unwrap_used)]
+
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, Float64Array, Int64Array, StringArray};
+use arrow::record_batch::RecordBatch;
+use rs_arrow_ipc_stream2x::{WriteOptions, batch_iter2x_with_options};
+use rust_xlsxwriter::Workbook;
+
+/// Returns a memory figure of the process in bytes, such as `VmRSS` or `VmHWM`.
+fn memory(key: &str) -> u64 {
+    let status = std::fs::read_to_string("/proc/self/status").unwrap();
+    let line = status
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))
+        .unwrap();
+    let kilobytes: u64 = line.trim().trim_end_matches(" kB").parse().unwrap();
+    kilobytes * 1024
+}
+
+/// Returns how much the peak memory of the process grows while running `f`.
+fn peak_growth(f: impl FnOnce()) -> u64 {
+    // Resets the peak to the current memory.
+    std::fs::write("/proc/self/clear_refs", "5").unwrap();
+    let before = memory("VmRSS");
+    f();
+    memory("VmHWM").saturating_sub(before)
+}
+
+/// A batch of a million rows of an integer, a float and a string column.
+fn enormous_batch() -> RecordBatch {
+    let rows = 0..1_000_000i64;
+    RecordBatch::try_from_iter([
+        (
+            "i",
+            Arc::new(Int64Array::from_iter_values(rows.clone())) as ArrayRef,
+        ),
+        (
+            "f",
+            Arc::new(Float64Array::from_iter_values(
+                rows.clone().map(|row| row as f64 / 7.0),
+            )) as ArrayRef,
+        ),
+        (
+            "s",
+            Arc::new(StringArray::from_iter_values(
+                rows.map(|row| format!("row {row}")),
+            )) as ArrayRef,
+        ),
+    ])
+    .unwrap()
+}
+
+#[test]
+fn constant_memory_mode_bounds_the_memory_of_one_enormous_batch() {
+    let batch = enormous_batch();
+    let write = |constant_memory: bool| {
+        let options = WriteOptions::builder()
+            .constant_memory(constant_memory)
+            .build();
+        peak_growth(|| {
+            let mut book = Workbook::new();
+            let summary = batch_iter2x_with_options(
+                [Ok(batch.clone())].into_iter(),
+                &mut book,
+                "S",
+                &options,
+            )
+            .unwrap();
+            assert_eq!(summary.rows_written, 1_000_000);
+        })
+    };
+    // Memory freed by a run is reused by the next, so the smaller run goes first.
+    let streamed = write(true);
+    let buffered = write(false);
+    // Buffering the million rows takes hundreds of MB, which shows that the growth is measured.
+    assert!(buffered > 256 << 20, "buffered: {buffered} bytes");
+    assert!(streamed < 64 << 20, "constant memory: {streamed} bytes");
+}